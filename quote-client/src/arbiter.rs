@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use quote_core::StockQuote;
+
+#[derive(Debug, Default)]
+pub struct Arbiter {
+    lines: HashMap<SocketAddr, HashMap<String, u64>>,
+    duplicates: u64,
+}
+
+impl Arbiter {
+    pub fn accept(&mut self, line: SocketAddr, quote: &StockQuote) -> bool {
+        let Some(seq) = quote.seq else {
+            return true;
+        };
+        let newest = self
+            .lines
+            .values()
+            .filter_map(|last| last.get(&quote.ticker))
+            .max()
+            .copied()
+            .unwrap_or(0);
+        let last = self.lines.entry(line).or_default();
+        last.insert(quote.ticker.clone(), seq);
+        if seq > newest {
+            true
+        } else {
+            self.duplicates += 1;
+            false
+        }
+    }
+
+    pub fn forget(&mut self, line: SocketAddr) {
+        self.lines.remove(&line);
+    }
+
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+
+    pub fn reset(&mut self) {
+        self.lines.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+
+    fn quote(seq: u64) -> StockQuote {
+        StockQuote {
+            ticker: "AAPL".to_string(),
+            price: 1.0,
+            volume: 1,
+            timestamp: 0,
+            seq: Some(seq),
+        }
+    }
+
+    const A: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 7001);
+    const B: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 7002);
+
+    #[test]
+    fn merges_two_lines_and_fills_gaps() {
+        let mut arbiter = Arbiter::default();
+        let line_a = [1, 2, 4, 5];
+        let line_b = [1, 3, 4, 6];
+        let mut merged = Vec::new();
+        for (a, b) in line_a.iter().zip(line_b.iter()) {
+            for (line, seq) in [(A, *a), (B, *b)] {
+                if arbiter.accept(line, &quote(seq)) {
+                    merged.push(seq);
+                }
+            }
+        }
+        assert_eq!(merged, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(arbiter.duplicates(), 2);
+    }
+
+    #[test]
+    fn restarted_line_is_not_held_back_by_old_seqs() {
+        let mut arbiter = Arbiter::default();
+        for seq in 1..=50 {
+            arbiter.accept(A, &quote(seq));
+            arbiter.accept(B, &quote(seq));
+        }
+        arbiter.forget(A);
+        assert!(!arbiter.accept(A, &quote(1)));
+        assert!(arbiter.accept(B, &quote(51)));
+        arbiter.forget(B);
+        assert!(arbiter.accept(A, &quote(2)));
+        assert!(arbiter.accept(A, &quote(3)));
+    }
+}
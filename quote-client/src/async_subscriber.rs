@@ -0,0 +1,391 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_core::Stream;
+use log::{debug, info, warn};
+use quote_core::tls::server_name;
+use quote_core::{ControlEvent, ControlKind, MarketEvent, StockQuote, StreamAccepted, parse_pong};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior, interval, sleep_until, timeout};
+use tokio_rustls::TlsConnector;
+
+use crate::client::{
+    BYE, TCP_CONNECT_TIMEOUT, TCP_READ_TIMEOUT, UDP_BIND_ADDR, UDP_READ_TIMEOUT, build_command,
+};
+use crate::subscriber::{RECV_BUF, SubscriberConfig, SubscriberError, check_reply};
+
+const STREAM_BUFFER: usize = 1024;
+
+type Item = Result<StockQuote, SubscriberError>;
+type ControlReader = Box<dyn AsyncRead + Send + Unpin>;
+type ControlWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+pub struct QuoteStream {
+    reply: String,
+    local_addr: SocketAddr,
+    rx: mpsc::Receiver<Item>,
+    task: JoinHandle<()>,
+}
+
+impl QuoteStream {
+    pub async fn connect(config: &SubscriberConfig) -> Result<Self, SubscriberError> {
+        if config.arbitrate {
+            return Err(SubscriberError::InvalidRequest(
+                "QuoteStream does not arbitrate between servers".to_string(),
+            ));
+        }
+        let socket = UdpSocket::bind((UDP_BIND_ADDR, config.udp_port)).await?;
+        let local_addr = socket.local_addr()?;
+        let command = build_command(
+            &config.udp_host,
+            local_addr.port(),
+            &config.tickers,
+            &config.options,
+        )
+        .map_err(SubscriberError::InvalidRequest)?;
+        let (read, mut write) = connect_first(&config.servers, config.tls.as_ref()).await?;
+        let mut control = BufReader::new(read);
+        let reply = match &config.auth {
+            Some(auth) => match exchange(&mut control, &mut write, &auth.to_string()).await? {
+                Some(reply) if reply.starts_with("OK") => {
+                    exchange(&mut control, &mut write, &command).await?
+                }
+                reply => reply,
+            },
+            None => exchange(&mut control, &mut write, &command).await?,
+        };
+        let accepted = check_reply(reply)?;
+        let reply = accepted.to_string();
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        let (closed_tx, control_closed) = oneshot::channel();
+        let receiver = Receiver {
+            socket,
+            accepted,
+            control: tokio::spawn(watch_control(control, closed_tx)),
+            control_closed,
+            _write: write,
+            filter: config.tickers.iter().cloned().collect(),
+            ping_interval: config.ping_interval,
+            silence_timeout: config.silence_timeout,
+            heartbeat_timeout: config.heartbeat_timeout,
+            heartbeats: false,
+            tx,
+        };
+        let task = tokio::spawn(receiver.run());
+        Ok(QuoteStream {
+            reply,
+            local_addr,
+            rx,
+            task,
+        })
+    }
+
+    pub fn reply(&self) -> &str {
+        &self.reply
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+async fn connect_first(
+    servers: &[String],
+    tls: Option<&Arc<rustls::ClientConfig>>,
+) -> Result<(ControlReader, ControlWriter), SubscriberError> {
+    let mut last_error = SubscriberError::NoServers;
+    for server in servers {
+        match timeout(TCP_CONNECT_TIMEOUT, connect(server, tls)).await {
+            Ok(Ok(halves)) => return Ok(halves),
+            Ok(Err(err)) => {
+                warn!("server {server} unavailable: {err}");
+                last_error = err.into();
+            }
+            Err(_) => {
+                warn!("server {server} unavailable: connect timed out");
+                last_error = SubscriberError::NoResponse;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+async fn connect(
+    server: &str,
+    tls: Option<&Arc<rustls::ClientConfig>>,
+) -> std::io::Result<(ControlReader, ControlWriter)> {
+    let stream = TcpStream::connect(server).await?;
+    match tls {
+        Some(config) => {
+            let connector = TlsConnector::from(config.clone());
+            let stream = connector.connect(server_name(server)?, stream).await?;
+            let (read, write) = tokio::io::split(stream);
+            Ok((Box::new(read), Box::new(write)))
+        }
+        None => {
+            let (read, write) = stream.into_split();
+            Ok((Box::new(read), Box::new(write)))
+        }
+    }
+}
+
+async fn exchange(
+    control: &mut BufReader<ControlReader>,
+    write: &mut ControlWriter,
+    line: &str,
+) -> Result<Option<String>, SubscriberError> {
+    write.write_all(format!("{line}\n").as_bytes()).await?;
+    write.flush().await?;
+    let mut buf = String::new();
+    match timeout(TCP_READ_TIMEOUT, control.read_line(&mut buf)).await {
+        Ok(Ok(0)) | Err(_) => Ok(None),
+        Ok(Ok(_)) => Ok(Some(buf.trim().to_string())),
+        Ok(Err(err)) => Err(err.into()),
+    }
+}
+
+async fn watch_control(control: BufReader<ControlReader>, closed: oneshot::Sender<()>) {
+    let mut lines = control.lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) if line.trim() == BYE => {
+                info!("server said BYE");
+                break;
+            }
+            Ok(Some(_)) => {}
+            Ok(None) | Err(_) => {
+                info!("control connection closed by server");
+                break;
+            }
+        }
+    }
+    let _ = closed.send(());
+}
+
+impl Stream for QuoteStream {
+    type Item = Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl Drop for QuoteStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct Receiver {
+    socket: UdpSocket,
+    accepted: StreamAccepted,
+    control: JoinHandle<()>,
+    control_closed: oneshot::Receiver<()>,
+    _write: ControlWriter,
+    filter: HashSet<String>,
+    ping_interval: Duration,
+    silence_timeout: Duration,
+    heartbeat_timeout: Duration,
+    heartbeats: bool,
+    tx: mpsc::Sender<Item>,
+}
+
+impl Receiver {
+    fn window(&self) -> Duration {
+        if self.heartbeats {
+            self.heartbeat_timeout
+        } else {
+            self.silence_timeout
+        }
+    }
+
+    async fn run(mut self) {
+        let mut buf = vec![0u8; RECV_BUF];
+        let mut control_open = true;
+        let grace = sleep_until(Instant::now());
+        let silence = sleep_until(Instant::now() + self.window());
+        tokio::pin!(grace, silence);
+        let mut pings = interval(self.ping_interval);
+        pings.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                received = self.socket.recv_from(&mut buf) => {
+                    let n = match received {
+                        Ok((n, _)) => n,
+                        Err(err) => {
+                            let _ = self.tx.send(Err(err.into())).await;
+                            return;
+                        }
+                    };
+                    if !self.handle(&buf[..n]).await {
+                        return;
+                    }
+                    silence.as_mut().reset(Instant::now() + self.window());
+                }
+                _ = &mut self.control_closed, if control_open => {
+                    control_open = false;
+                    grace.as_mut().reset(Instant::now() + UDP_READ_TIMEOUT);
+                }
+                _ = pings.tick() => {
+                    let ping = self.accepted.ticket.ping();
+                    let _ = self.socket.send_to(ping.as_bytes(), self.accepted.endpoint).await;
+                }
+                _ = &mut silence => {
+                    let window = self.window();
+                    if self.heartbeats {
+                        warn!("missed heartbeats for {window:?}");
+                    } else {
+                        warn!("no data for {window:?}");
+                    }
+                    let _ = self.tx.send(Err(SubscriberError::Silent(window))).await;
+                    return;
+                }
+                _ = &mut grace, if !control_open => return,
+                _ = self.tx.closed() => return,
+            }
+        }
+    }
+
+    async fn handle(&mut self, datagram: &[u8]) -> bool {
+        let Ok(payload) = std::str::from_utf8(datagram) else {
+            return true;
+        };
+        if let Some(id) = parse_pong(payload) {
+            debug!("pong for stream {id}");
+            return true;
+        }
+        match MarketEvent::from_string(payload) {
+            Some(MarketEvent::Quote(quote)) if self.filter.contains(&quote.ticker) => {
+                self.tx.send(Ok(quote)).await.is_ok()
+            }
+            Some(MarketEvent::Quote(quote)) => {
+                warn!("filtered {}", quote.ticker);
+                true
+            }
+            Some(MarketEvent::Control(ControlEvent {
+                control: ControlKind::Heartbeat,
+                ..
+            })) => {
+                self.heartbeats = true;
+                true
+            }
+            Some(MarketEvent::Control(ControlEvent {
+                control: ControlKind::Challenge,
+                nonce: Some(nonce),
+                ..
+            })) => {
+                let proof = self.accepted.ticket.proof(&nonce);
+                let _ = self
+                    .socket
+                    .send_to(proof.as_bytes(), self.accepted.endpoint)
+                    .await;
+                true
+            }
+            Some(MarketEvent::Control(ControlEvent {
+                control: ControlKind::Shutdown,
+                ..
+            })) => false,
+            Some(MarketEvent::Control(_)) => true,
+            Some(_) => true,
+            None => {
+                warn!("unparsed payload: {payload}");
+                true
+            }
+        }
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        self.control.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::poll_fn;
+
+    use std::io::{BufRead, Write};
+    use std::net::TcpListener;
+
+    use quote_core::{QuoteGenerator, StreamTicket};
+    use quote_server::QuoteServer;
+
+    use super::*;
+
+    async fn next(stream: &mut QuoteStream) -> Option<Item> {
+        poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    #[tokio::test]
+    async fn streams_quotes_until_shutdown() {
+        let server = QuoteServer::builder()
+            .bind("127.0.0.1:0")
+            .source(Box::new(QuoteGenerator::default()))
+            .start()
+            .unwrap();
+        let mut config = SubscriberConfig::new(&[&server.local_addr().to_string()], &["msft"]);
+        config.arbitrate = true;
+        assert!(matches!(
+            QuoteStream::connect(&config).await,
+            Err(SubscriberError::InvalidRequest(_))
+        ));
+        config.arbitrate = false;
+        let mut stream = QuoteStream::connect(&config).await.unwrap();
+        let accepted = quote_core::parse_accepted(stream.reply()).unwrap();
+        assert_eq!(accepted.endpoint.ip(), server.local_addr().ip());
+
+        let quote = next(&mut stream).await.unwrap().unwrap();
+        assert_eq!(quote.ticker, "MSFT");
+
+        tokio::task::spawn_blocking(move || server.shutdown())
+            .await
+            .unwrap()
+            .unwrap();
+        while let Some(item) = next(&mut stream).await {
+            assert!(item.is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_silence_before_any_heartbeat() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (control, _) = listener.accept().unwrap();
+            let mut reader = std::io::BufReader::new(control.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let accepted = StreamAccepted {
+                ticket: StreamTicket {
+                    id: 1,
+                    token: "quiet".to_string(),
+                },
+                endpoint: addr,
+            };
+            let mut control = control;
+            control
+                .write_all(format!("{accepted}\n").as_bytes())
+                .unwrap();
+            let _ = reader.read_line(&mut line);
+        });
+        let mut config = SubscriberConfig::new(&[&addr.to_string()], &["AAPL"]);
+        config.silence_timeout = Duration::from_millis(200);
+        let mut stream = QuoteStream::connect(&config).await.unwrap();
+
+        let item = timeout(Duration::from_secs(3), next(&mut stream)).await;
+        assert!(matches!(
+            item,
+            Ok(Some(Err(SubscriberError::Silent(window)))) if window == config.silence_timeout
+        ));
+        drop(stream);
+        server.join().unwrap();
+    }
+}
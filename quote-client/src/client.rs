@@ -1,14 +1,21 @@
 use std::fs;
-use std::io::{BufRead, BufReader, Write};
-use std::net::{TcpStream, UdpSocket};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
 use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
-use quote_core::parse_command;
+use log::info;
+use quote_core::{AuthRequest, StreamOptions, parse_command};
 
-const TCP_READ_TIMEOUT: Duration = Duration::from_secs(3);
-const UDP_READ_TIMEOUT: Duration = Duration::from_millis(500);
-const UDP_BIND_ADDR: &str = "0.0.0.0";
+use crate::tls::Control;
+
+pub(crate) const TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+pub(crate) const TCP_READ_TIMEOUT: Duration = Duration::from_secs(3);
+pub(crate) const UDP_READ_TIMEOUT: Duration = Duration::from_millis(500);
+pub(crate) const UDP_BIND_ADDR: &str = "0.0.0.0";
+pub(crate) const BYE: &str = "BYE";
 
 pub fn load_tickers(path: &Path) -> Result<Vec<String>, String> {
     let data = fs::read_to_string(path).map_err(|e| e.to_string())?;
@@ -24,31 +31,82 @@ pub fn load_tickers(path: &Path) -> Result<Vec<String>, String> {
     Ok(tickers)
 }
 
-pub fn build_command(host: &str, port: u16, tickers: &[String]) -> Result<String, String> {
+pub fn build_command(
+    host: &str,
+    port: u16,
+    tickers: &[String],
+    options: &StreamOptions,
+) -> Result<String, String> {
     if tickers.is_empty() {
         return Err("no tickers to request".to_string());
     }
     let joined = tickers.join(",");
-    let command = format!("STREAM udp://{}:{} {}", host, port, joined);
+    let command = format!("STREAM udp://{}:{} {}{}", host, port, joined, options);
     parse_command(&command).map_err(|e| e.to_string())?;
     Ok(command)
 }
 
-pub fn send_command(server: &str, command: &str) -> std::io::Result<Option<String>> {
-    let mut stream = TcpStream::connect(server)?;
-    let payload = format!("{command}\n");
-    stream.write_all(payload.as_bytes())?;
-    stream.flush()?;
+pub fn open_stream(
+    server: &str,
+    tls: Option<&Arc<rustls::ClientConfig>>,
+    auth: Option<&AuthRequest>,
+    command: &str,
+) -> std::io::Result<(Option<String>, BufReader<Control>)> {
+    let addr = server
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "unresolved server"))?;
+    let stream = TcpStream::connect_timeout(&addr, TCP_CONNECT_TIMEOUT)?;
     stream.set_read_timeout(Some(TCP_READ_TIMEOUT)).ok();
+    let mut reader = BufReader::new(Control::connect(stream, server, tls)?);
+    if let Some(auth) = auth {
+        match exchange(&mut reader, &auth.to_string())? {
+            Some(reply) if reply.starts_with("OK") => {}
+            reply => return Ok((reply, reader)),
+        }
+    }
+    let reply = exchange(&mut reader, command)?;
+    Ok((reply, reader))
+}
+
+fn exchange(reader: &mut BufReader<Control>, line: &str) -> std::io::Result<Option<String>> {
+    let stream = reader.get_mut();
+    stream.write_all(format!("{line}\n").as_bytes())?;
+    stream.flush()?;
     let mut buf = String::new();
-    let mut reader = BufReader::new(stream);
-    match reader.read_line(&mut buf) {
-        Ok(0) => Ok(None),
-        Ok(_) => Ok(Some(buf.trim().to_string())),
-        Err(e) => Err(e),
+    match reader.read_line(&mut buf)? {
+        0 => Ok(None),
+        _ => Ok(Some(buf.trim().to_string())),
     }
 }
 
+pub fn watch_control(mut control: BufReader<Control>, running: Arc<AtomicBool>) {
+    control
+        .get_ref()
+        .socket()
+        .set_read_timeout(Some(UDP_READ_TIMEOUT))
+        .ok();
+    let mut line = String::new();
+    while running.load(Ordering::SeqCst) {
+        match control.read_line(&mut line) {
+            Ok(0) => {
+                info!("control connection closed by server");
+                break;
+            }
+            Ok(_) if line.trim() == BYE => {
+                info!("server said BYE");
+                break;
+            }
+            Ok(_) => line.clear(),
+            Err(ref e)
+                if e.kind() == std::io::ErrorKind::WouldBlock
+                    || e.kind() == std::io::ErrorKind::TimedOut => {}
+            Err(_) => break,
+        }
+    }
+    running.store(false, Ordering::SeqCst);
+}
+
 pub fn bind_udp(port: u16) -> std::io::Result<UdpSocket> {
     let socket = UdpSocket::bind((UDP_BIND_ADDR, port))?;
     socket.set_read_timeout(Some(UDP_READ_TIMEOUT))?;
@@ -77,8 +135,16 @@ mod tests {
     #[test]
     fn builds_valid_command() {
         let tickers = vec!["AAPL".to_string(), "TSLA".to_string()];
-        let cmd = build_command("127.0.0.1", 4000, &tickers).unwrap();
+        let cmd = build_command("127.0.0.1", 4000, &tickers, &StreamOptions::default()).unwrap();
         assert_eq!(cmd, "STREAM udp://127.0.0.1:4000 AAPL,TSLA");
+        let options = StreamOptions {
+            depth: true,
+            bars: Some(Duration::from_secs(60)),
+            conflate: None,
+            overflow: None,
+        };
+        let cmd = build_command("127.0.0.1", 4000, &tickers, &options).unwrap();
+        assert_eq!(cmd, "STREAM udp://127.0.0.1:4000 AAPL,TSLA DEPTH BARS 1m");
     }
 
     #[test]
@@ -99,7 +165,40 @@ mod tests {
                 let _ = stream.write_all(b"OK\n");
             }
         });
-        let result = send_command(&addr.to_string(), "STREAM udp://127.0.0.1:1234 AAPL");
-        assert_eq!(result.unwrap(), Some("OK".to_string()));
+        let (reply, _) = open_stream(
+            &addr.to_string(),
+            None,
+            None,
+            "STREAM udp://127.0.0.1:1234 AAPL",
+        )
+        .unwrap();
+        assert_eq!(reply, Some("OK".to_string()));
+    }
+
+    #[test]
+    fn stops_on_bye() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            if let Ok((mut stream, _)) = listener.accept() {
+                let mut buf = [0u8; 64];
+                let _ = stream.read(&mut buf);
+                let _ = stream.write_all(b"OK\n");
+                thread::sleep(Duration::from_millis(50));
+                let _ = stream.write_all(b"BYE\n");
+                thread::sleep(Duration::from_millis(500));
+            }
+        });
+        let (reply, control) = open_stream(
+            &addr.to_string(),
+            None,
+            None,
+            "STREAM udp://127.0.0.1:1234 AAPL",
+        )
+        .unwrap();
+        assert_eq!(reply.as_deref(), Some("OK"));
+        let running = Arc::new(AtomicBool::new(true));
+        watch_control(control, running.clone());
+        assert!(!running.load(Ordering::SeqCst));
     }
 }
@@ -0,0 +1,11 @@
+pub mod arbiter;
+#[cfg(feature = "async")]
+pub mod async_subscriber;
+pub mod client;
+pub mod subscriber;
+pub mod tls;
+
+pub use arbiter::Arbiter;
+#[cfg(feature = "async")]
+pub use async_subscriber::QuoteStream;
+pub use subscriber::{Backoff, QuoteSubscriber, StopHandle, SubscriberConfig, SubscriberError};
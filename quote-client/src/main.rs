@@ -1,134 +1,198 @@
-mod client;
-
-use std::collections::HashSet;
-use std::net::SocketAddr;
-use std::sync::{
-    Arc,
-    atomic::{AtomicBool, Ordering},
-    mpsc,
-};
-use std::thread;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::time::Duration;
 
-use crate::client::{bind_udp, build_command, load_tickers, send_command};
 use clap::Parser;
 use log::{error, info, warn};
-use quote_core::StockQuote;
+use quote_client::client::load_tickers;
+use quote_client::subscriber::DEFAULT_PING_INTERVAL;
+use quote_client::tls::client_config;
+use quote_client::{Backoff, QuoteSubscriber, SubscriberConfig, SubscriberError};
+use quote_core::{
+    AuthRequest, MarketEvent, OrderBook, OverflowPolicy, StreamOptions, parse_duration,
+};
 
 const DEFAULT_SERVER_ADDR: &str = "127.0.0.1:7878";
 const DEFAULT_UDP_HOST: &str = "127.0.0.1";
 const DEFAULT_UDP_PORT: u16 = 34254;
-const PING_INTERVAL: Duration = Duration::from_secs(2);
-const SRC_WAIT: Duration = Duration::from_millis(500);
-const RECV_BUF: usize = 1024;
 
 #[derive(Parser, Debug)]
 struct Args {
-    #[arg(long = "server-addr", alias = "server", default_value = DEFAULT_SERVER_ADDR)]
-    server_addr: String,
+    #[arg(
+        long = "server-addr",
+        alias = "server",
+        value_delimiter = ',',
+        default_value = DEFAULT_SERVER_ADDR
+    )]
+    server_addr: Vec<String>,
     #[arg(long = "udp-host", default_value = DEFAULT_UDP_HOST)]
     udp_host: String,
     #[arg(long = "udp-port", default_value_t = DEFAULT_UDP_PORT)]
     udp_port: u16,
     #[arg(long = "tickers-file", alias = "tickers")]
-    tickers_file: std::path::PathBuf,
+    tickers_file: PathBuf,
+    #[arg(long = "user", requires = "token")]
+    user: Option<String>,
+    #[arg(long = "token", requires = "user")]
+    token: Option<String>,
+    #[arg(long = "tls-ca")]
+    tls_ca: Option<PathBuf>,
+    #[arg(long = "tls-cert", requires_all = ["tls_ca", "tls_key"])]
+    tls_cert: Option<PathBuf>,
+    #[arg(long = "tls-key", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    #[arg(long = "depth")]
+    depth: bool,
+    #[arg(long = "bars", value_parser = parse_interval)]
+    bars: Option<Duration>,
+    #[arg(long = "conflate", value_parser = parse_interval, conflicts_with = "bars")]
+    conflate: Option<Duration>,
+    #[arg(long = "policy")]
+    policy: Option<OverflowPolicy>,
+    #[arg(long = "silence-timeout", value_parser = parse_interval, default_value = "6s")]
+    silence_timeout: Duration,
+    #[arg(long = "heartbeat-timeout", value_parser = parse_interval, default_value = "3s")]
+    heartbeat_timeout: Duration,
+    #[arg(long = "reconnect-max", value_parser = parse_interval, default_value = "30s")]
+    reconnect_max: Duration,
+    #[arg(long = "no-reconnect")]
+    no_reconnect: bool,
+    #[arg(long = "arbitrate")]
+    arbitrate: bool,
 }
 
 fn main() -> std::io::Result<()> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
     let args = Args::parse();
-    let tickers = load_tickers(&args.tickers_file).map_err(io_error)?;
-    let command = build_command(&args.udp_host, args.udp_port, &tickers).map_err(io_error)?;
-    let response = send_command(&args.server_addr, &command)?;
-    match response {
-        Some(resp) if resp.starts_with("ERR") => {
-            error!("{resp}");
+    let tls = match &args.tls_ca {
+        Some(ca) => {
+            let identity = args.tls_cert.as_deref().zip(args.tls_key.as_deref());
+            Some(client_config(ca, identity).map_err(io_error)?)
+        }
+        None => None,
+    };
+    let config = SubscriberConfig {
+        servers: args.server_addr,
+        udp_host: args.udp_host,
+        udp_port: args.udp_port,
+        tickers: load_tickers(&args.tickers_file).map_err(io_error)?,
+        auth: args
+            .user
+            .zip(args.token)
+            .map(|(user, token)| AuthRequest { user, token }),
+        tls,
+        options: StreamOptions {
+            depth: args.depth,
+            bars: args.bars,
+            conflate: args.conflate,
+            overflow: args.policy,
+        },
+        ping_interval: DEFAULT_PING_INTERVAL,
+        silence_timeout: args.silence_timeout,
+        heartbeat_timeout: args.heartbeat_timeout,
+        reconnect: (!args.no_reconnect).then(|| Backoff {
+            max: args.reconnect_max,
+            ..Backoff::default()
+        }),
+        arbitrate: args.arbitrate,
+    };
+    let subscriber = match QuoteSubscriber::connect(&config) {
+        Ok(subscriber) => subscriber,
+        Err(SubscriberError::Io(err)) => return Err(err),
+        Err(err) => {
+            error!("{err}");
             return Ok(());
         }
-        Some(resp) => info!("{resp}"),
-        None => warn!("no response from server"),
-    }
-    let socket = bind_udp(args.udp_port)?;
-    let running = Arc::new(AtomicBool::new(true));
-    let (src_tx, src_rx) = mpsc::channel::<SocketAddr>();
-    let ping_socket = socket.try_clone()?;
-    let ping_running = running.clone();
-    let ping_handle = thread::spawn(move || ping_loop(ping_socket, ping_running, src_rx));
-    let filter = tickers.into_iter().collect::<HashSet<_>>();
+    };
+    info!("{}", subscriber.reply());
     ctrlc::set_handler({
-        let running = running.clone();
-        move || {
-            running.store(false, Ordering::SeqCst);
-        }
+        let stop = subscriber.stop_handle();
+        move || stop.stop()
     })
     .ok();
-    recv_loop(socket, running.clone(), src_tx, filter);
-    running.store(false, Ordering::SeqCst);
-    let _ = ping_handle.join();
+    let mut books: HashMap<String, OrderBook> = HashMap::new();
+    if let Err(err) = subscriber.run(|event| print_event(event, &mut books)) {
+        error!("udp receive error: {err}");
+    }
     Ok(())
 }
 
-fn recv_loop(
-    socket: std::net::UdpSocket,
-    running: Arc<AtomicBool>,
-    src_tx: mpsc::Sender<SocketAddr>,
-    filter: HashSet<String>,
-) {
-    let mut buf = [0u8; RECV_BUF];
-    while running.load(Ordering::SeqCst) {
-        match socket.recv_from(&mut buf) {
-            Ok((n, src)) => {
-                let _ = src_tx.send(src);
-                if let Ok(msg) = std::str::from_utf8(&buf[..n]) {
-                    if let Some(quote) = StockQuote::from_string(msg) {
-                        if filter.contains(&quote.ticker) {
-                            println!(
-                                "{} price={:.2} volume={} ts={}",
-                                quote.ticker, quote.price, quote.volume, quote.timestamp
-                            );
-                        } else {
-                            warn!("filtered {}", quote.ticker);
-                        }
-                    } else {
-                        warn!("unparsed payload: {msg}");
-                    }
+fn print_event(event: MarketEvent, books: &mut HashMap<String, OrderBook>) {
+    match event {
+        MarketEvent::Quote(quote) => {
+            println!(
+                "{} price={:.2} volume={} ts={}",
+                quote.ticker, quote.price, quote.volume, quote.timestamp
+            );
+        }
+        MarketEvent::Status(status) => {
+            println!(
+                "{} status={:?} reason={} ts={}",
+                status.ticker,
+                status.status,
+                status.reason.unwrap_or_default(),
+                status.timestamp
+            );
+        }
+        MarketEvent::Session(session) => {
+            println!("session={:?} ts={}", session.session, session.timestamp);
+        }
+        MarketEvent::Book(snapshot) => {
+            let book = OrderBook::from_snapshot(&snapshot);
+            print_top(&book);
+            books.insert(snapshot.ticker, book);
+        }
+        MarketEvent::BookUpdate(update) => {
+            if let Some(book) = books.get_mut(&update.ticker) {
+                if book.apply(&update) {
+                    print_top(book);
+                } else {
+                    warn!("book gap {} seq={}", update.ticker, update.seq);
+                    books.remove(&update.ticker);
                 }
             }
-            Err(ref e)
-                if e.kind() == std::io::ErrorKind::WouldBlock
-                    || e.kind() == std::io::ErrorKind::TimedOut => {}
-            Err(e) => {
-                error!("udp receive error: {e}");
-                break;
-            }
+        }
+        MarketEvent::Bar(bar) => {
+            println!(
+                "{} bar start={} o={:.2} h={:.2} l={:.2} c={:.2} v={}{}",
+                bar.ticker,
+                bar.start,
+                bar.open,
+                bar.high,
+                bar.low,
+                bar.close,
+                bar.volume,
+                if bar.complete { "" } else { " (open)" }
+            );
+        }
+        MarketEvent::Control(control) => {
+            info!(
+                "server: {:?} {}",
+                control.control,
+                control.reason.unwrap_or_default()
+            );
         }
     }
 }
 
-fn ping_loop(
-    socket: std::net::UdpSocket,
-    running: Arc<AtomicBool>,
-    src_rx: mpsc::Receiver<SocketAddr>,
-) {
-    let mut target: Option<SocketAddr> = None;
-    while running.load(Ordering::SeqCst) {
-        if target.is_none() {
-            match src_rx.recv_timeout(SRC_WAIT) {
-                Ok(addr) => target = Some(addr),
-                Err(mpsc::RecvTimeoutError::Timeout) => continue,
-                Err(_) => break,
-            }
-        }
-        if let Ok(new_addr) = src_rx.try_recv() {
-            target = Some(new_addr);
-        }
-        if let Some(addr) = target {
-            let _ = socket.send_to(b"Ping", addr);
-        }
-        thread::sleep(PING_INTERVAL);
+fn print_top(book: &OrderBook) {
+    if let (Some(bid), Some(ask)) = (book.best_bid(), book.best_ask()) {
+        println!(
+            "{} book bid={:.2}x{} ask={:.2}x{} seq={}",
+            book.ticker(),
+            bid.price,
+            bid.size,
+            ask.price,
+            ask.size,
+            book.seq()
+        );
     }
 }
 
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    parse_duration(value).ok_or_else(|| format!("invalid interval: {value}"))
+}
+
 fn io_error<T: ToString>(msg: T) -> std::io::Error {
-    std::io::Error::new(std::io::ErrorKind::Other, msg.to_string())
+    std::io::Error::other(msg.to_string())
 }
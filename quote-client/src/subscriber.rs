@@ -0,0 +1,845 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::io::BufReader;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+use quote_core::{
+    AuthRequest, ControlEvent, ControlKind, MarketEvent, StockQuote, StreamAccepted, StreamOptions,
+    parse_accepted, parse_pong,
+};
+
+use crate::arbiter::Arbiter;
+use crate::client::{bind_udp, build_command, open_stream, watch_control};
+use crate::tls::Control;
+
+pub const DEFAULT_UDP_HOST: &str = "127.0.0.1";
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(2);
+pub const DEFAULT_SILENCE_TIMEOUT: Duration = Duration::from_secs(6);
+pub const DEFAULT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(3);
+const PING_POLL: Duration = Duration::from_millis(100);
+pub(crate) const RECV_BUF: usize = 65536;
+
+#[derive(Debug, Clone)]
+pub struct SubscriberConfig {
+    pub servers: Vec<String>,
+    pub udp_host: String,
+    pub udp_port: u16,
+    pub tickers: Vec<String>,
+    pub auth: Option<AuthRequest>,
+    pub tls: Option<Arc<rustls::ClientConfig>>,
+    pub options: StreamOptions,
+    pub ping_interval: Duration,
+    pub silence_timeout: Duration,
+    pub heartbeat_timeout: Duration,
+    pub reconnect: Option<Backoff>,
+    pub arbitrate: bool,
+}
+
+impl SubscriberConfig {
+    pub fn new(servers: &[&str], tickers: &[&str]) -> Self {
+        SubscriberConfig {
+            servers: servers.iter().map(|s| s.to_string()).collect(),
+            udp_host: DEFAULT_UDP_HOST.to_string(),
+            udp_port: 0,
+            tickers: tickers.iter().map(|t| t.to_uppercase()).collect(),
+            auth: None,
+            tls: None,
+            options: StreamOptions::default(),
+            ping_interval: DEFAULT_PING_INTERVAL,
+            silence_timeout: DEFAULT_SILENCE_TIMEOUT,
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            reconnect: Some(Backoff::default()),
+            arbitrate: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    pub fn first(&self) -> Duration {
+        self.initial.min(self.max)
+    }
+
+    pub fn next(&self, delay: Duration) -> Duration {
+        (delay * 2).min(self.max).max(self.first())
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SubscriberError {
+    Io(std::io::Error),
+    InvalidRequest(String),
+    Rejected(String),
+    InvalidReply(String),
+    NoResponse,
+    Silent(Duration),
+    NoServers,
+}
+
+impl fmt::Display for SubscriberError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubscriberError::Io(err) => write!(f, "io error: {err}"),
+            SubscriberError::InvalidRequest(err) => write!(f, "invalid request: {err}"),
+            SubscriberError::Rejected(reply) => write!(f, "server rejected stream: {reply}"),
+            SubscriberError::InvalidReply(reply) => write!(f, "invalid server reply: {reply}"),
+            SubscriberError::NoResponse => write!(f, "no response from server"),
+            SubscriberError::Silent(window) => write!(f, "no data from server for {window:?}"),
+            SubscriberError::NoServers => write!(f, "no servers configured"),
+        }
+    }
+}
+
+impl std::error::Error for SubscriberError {}
+
+impl From<std::io::Error> for SubscriberError {
+    fn from(err: std::io::Error) -> Self {
+        SubscriberError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    pub fn stop(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        !self.0.load(Ordering::SeqCst)
+    }
+}
+
+struct Line {
+    server: usize,
+    accepted: StreamAccepted,
+    open: Arc<AtomicBool>,
+    watcher: Option<thread::JoinHandle<()>>,
+    last_seen: Instant,
+    heartbeats: bool,
+}
+
+impl Line {
+    fn window(&self, config: &SubscriberConfig) -> Duration {
+        if self.heartbeats {
+            config.heartbeat_timeout
+        } else {
+            config.silence_timeout
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.open.load(Ordering::SeqCst)
+    }
+
+    fn close(&mut self) {
+        self.open.store(false, Ordering::SeqCst);
+    }
+
+    fn join(&mut self) {
+        if let Some(handle) = self.watcher.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+pub struct QuoteSubscriber {
+    config: SubscriberConfig,
+    command: String,
+    socket: UdpSocket,
+    local_addr: SocketAddr,
+    reply: String,
+    filter: HashSet<String>,
+    running: Arc<AtomicBool>,
+    tickets: Arc<Mutex<Vec<StreamAccepted>>>,
+    pinger: Option<thread::JoinHandle<()>>,
+    lines: Vec<Line>,
+    cursor: usize,
+    arbiter: Option<Arbiter>,
+    last_check: Instant,
+    retry_at: Instant,
+    retry_delay: Duration,
+    buf: Vec<u8>,
+}
+
+impl QuoteSubscriber {
+    pub fn connect(config: &SubscriberConfig) -> Result<Self, SubscriberError> {
+        if config.servers.is_empty() {
+            return Err(SubscriberError::NoServers);
+        }
+        let socket = bind_udp(config.udp_port)?;
+        let local_addr = socket.local_addr()?;
+        let command = build_command(
+            &config.udp_host,
+            local_addr.port(),
+            &config.tickers,
+            &config.options,
+        )
+        .map_err(SubscriberError::InvalidRequest)?;
+        let running = Arc::new(AtomicBool::new(true));
+        let tickets = Arc::new(Mutex::new(Vec::new()));
+        let pinger = spawn_pinger(
+            socket.try_clone()?,
+            running.clone(),
+            tickets.clone(),
+            config.ping_interval,
+        );
+        let backoff = config.reconnect.unwrap_or_default();
+        let mut subscriber = QuoteSubscriber {
+            config: config.clone(),
+            command,
+            socket,
+            local_addr,
+            reply: String::new(),
+            filter: config.tickers.iter().cloned().collect(),
+            running,
+            tickets,
+            pinger: Some(pinger),
+            lines: Vec::new(),
+            cursor: 0,
+            arbiter: config.arbitrate.then(Arbiter::default),
+            last_check: Instant::now(),
+            retry_at: Instant::now(),
+            retry_delay: backoff.first(),
+            buf: vec![0u8; RECV_BUF],
+        };
+        subscriber.fill_lines()?;
+        Ok(subscriber)
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn reply(&self) -> &str {
+        &self.reply
+    }
+
+    pub fn active_servers(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .map(|line| self.config.servers[line.server].as_str())
+            .collect()
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle(self.running.clone())
+    }
+
+    pub fn next_event(&mut self) -> Result<Option<MarketEvent>, SubscriberError> {
+        while self.running.load(Ordering::SeqCst) {
+            let open = self.lines.iter().filter(|line| line.is_open()).count();
+            if open > 0
+                && (open < self.lines.len()
+                    || open < self.wanted_lines()
+                    || self.last_check.elapsed() >= PING_POLL)
+            {
+                self.check_liveness()?;
+            }
+            let (n, src) = match self.socket.recv_from(&mut self.buf) {
+                Ok(received) => received,
+                Err(ref err)
+                    if matches!(
+                        err.kind(),
+                        std::io::ErrorKind::WouldBlock
+                            | std::io::ErrorKind::TimedOut
+                            | std::io::ErrorKind::Interrupted
+                    ) =>
+                {
+                    self.check_liveness()?;
+                    continue;
+                }
+                Err(err) => {
+                    self.stop_handle().stop();
+                    return Err(err.into());
+                }
+            };
+            let line = self.line_from(src);
+            if let Some(index) = line {
+                self.lines[index].last_seen = Instant::now();
+            }
+            let Ok(payload) = std::str::from_utf8(&self.buf[..n]) else {
+                continue;
+            };
+            if let Some(id) = parse_pong(payload) {
+                debug!("pong for stream {id} from {src}");
+                continue;
+            }
+            match MarketEvent::from_string(payload) {
+                Some(MarketEvent::Quote(quote)) if !self.filter.contains(&quote.ticker) => {
+                    warn!("filtered {}", quote.ticker);
+                }
+                Some(MarketEvent::Quote(quote))
+                    if self
+                        .arbiter
+                        .as_mut()
+                        .is_some_and(|arbiter| !arbiter.accept(src, &quote)) => {}
+                Some(MarketEvent::Control(ControlEvent {
+                    control: ControlKind::Heartbeat,
+                    seqs,
+                    ..
+                })) => {
+                    debug!("heartbeat from {src}, last seqs {seqs:?}");
+                    if let Some(index) = line {
+                        self.lines[index].heartbeats = true;
+                    }
+                }
+                Some(MarketEvent::Control(ControlEvent {
+                    control: ControlKind::Challenge,
+                    nonce: Some(nonce),
+                    ..
+                })) => {
+                    debug!("challenge from {src}");
+                    match line {
+                        Some(index) => {
+                            let proof = self.lines[index].accepted.ticket.proof(&nonce);
+                            let _ = self.socket.send_to(proof.as_bytes(), src);
+                        }
+                        None => warn!("challenge from unknown server {src}"),
+                    }
+                }
+                Some(event) => return Ok(Some(event)),
+                None => warn!("unparsed payload: {payload}"),
+            }
+        }
+        Ok(None)
+    }
+
+    fn line_from(&self, src: SocketAddr) -> Option<usize> {
+        self.lines
+            .iter()
+            .position(|line| line.accepted.endpoint == src)
+            .or_else(|| (self.lines.len() == 1).then_some(0))
+    }
+
+    fn check_liveness(&mut self) -> Result<(), SubscriberError> {
+        self.last_check = Instant::now();
+        let mut silent = None;
+        for line in &mut self.lines {
+            let window = line.window(&self.config);
+            if line.is_open() && line.last_seen.elapsed() > window {
+                let server = &self.config.servers[line.server];
+                if line.heartbeats {
+                    warn!("missed heartbeats from {server} for {window:?}");
+                } else {
+                    warn!("no data from {server} for {window:?}");
+                }
+                line.close();
+                silent = Some(window);
+            }
+        }
+        self.drop_closed_lines();
+        let Some(backoff) = self.config.reconnect else {
+            if self.lines.is_empty() {
+                self.stop_handle().stop();
+                if let Some(window) = silent {
+                    return Err(SubscriberError::Silent(window));
+                }
+            }
+            return Ok(());
+        };
+        if self.lines.is_empty() {
+            self.reconnect(backoff);
+        } else if self.lines.len() < self.wanted_lines() && Instant::now() >= self.retry_at {
+            if self.fill_lines().is_ok() && self.lines.len() == self.wanted_lines() {
+                self.retry_delay = backoff.first();
+            } else {
+                self.retry_delay = backoff.next(self.retry_delay);
+            }
+            self.retry_at = Instant::now() + self.retry_delay;
+        }
+        Ok(())
+    }
+
+    fn drop_closed_lines(&mut self) {
+        let before = self.lines.len();
+        for line in self.lines.iter_mut().filter(|line| !line.is_open()) {
+            warn!("connection to {} lost", self.config.servers[line.server]);
+            line.close();
+            if let Some(arbiter) = self.arbiter.as_mut() {
+                arbiter.forget(line.accepted.endpoint);
+            }
+        }
+        self.lines.retain(Line::is_open);
+        if self.lines.len() != before {
+            self.update_tickets();
+        }
+    }
+
+    fn update_tickets(&self) {
+        if let Ok(mut tickets) = self.tickets.lock() {
+            *tickets = self
+                .lines
+                .iter()
+                .map(|line| line.accepted.clone())
+                .collect();
+        }
+    }
+
+    fn wanted_lines(&self) -> usize {
+        if self.config.arbitrate {
+            self.config.servers.len().min(2)
+        } else {
+            1
+        }
+    }
+
+    fn fill_lines(&mut self) -> Result<(), SubscriberError> {
+        let mut last_error = None;
+        for _ in 0..self.config.servers.len() {
+            if self.lines.len() >= self.wanted_lines() {
+                break;
+            }
+            let server = self.cursor;
+            self.cursor = (self.cursor + 1) % self.config.servers.len();
+            if self.lines.iter().any(|line| line.server == server) {
+                continue;
+            }
+            let addr = &self.config.servers[server];
+            match open_line(server, addr, &self.config, &self.command) {
+                Ok(line) => {
+                    info!("streaming from {addr}: {}", line.accepted);
+                    if let Some(arbiter) = self.arbiter.as_mut() {
+                        arbiter.forget(line.accepted.endpoint);
+                    }
+                    self.reply = line.accepted.to_string();
+                    self.lines.push(line);
+                    self.update_tickets();
+                }
+                Err(err) => {
+                    warn!("server {addr} unavailable: {err}");
+                    last_error = Some(err);
+                }
+            }
+        }
+        match (self.lines.is_empty(), last_error) {
+            (true, Some(err)) => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn reconnect(&mut self, backoff: Backoff) {
+        if let Some(arbiter) = self.arbiter.as_mut() {
+            arbiter.reset();
+        }
+        let mut delay = backoff.first();
+        let mut attempt = 1;
+        while self.running.load(Ordering::SeqCst) {
+            if self.fill_lines().is_ok() && !self.lines.is_empty() {
+                info!(
+                    "reconnected to {} after {attempt} attempt(s)",
+                    self.active_servers().join(", ")
+                );
+                return;
+            }
+            warn!("reconnect attempt {attempt} failed; retrying in {delay:?}");
+            self.sleep(delay);
+            delay = backoff.next(delay);
+            attempt += 1;
+        }
+    }
+
+    fn sleep(&self, delay: Duration) {
+        let deadline = Instant::now() + delay;
+        while self.running.load(Ordering::SeqCst) && Instant::now() < deadline {
+            thread::sleep(PING_POLL.min(deadline - Instant::now()));
+        }
+    }
+
+    pub fn events(&mut self) -> impl Iterator<Item = Result<MarketEvent, SubscriberError>> + '_ {
+        std::iter::from_fn(move || self.next_event().transpose())
+    }
+
+    pub fn quotes(&mut self) -> impl Iterator<Item = Result<StockQuote, SubscriberError>> + '_ {
+        self.events().filter_map(|event| match event {
+            Ok(MarketEvent::Quote(quote)) => Some(Ok(quote)),
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        })
+    }
+
+    pub fn run(mut self, mut callback: impl FnMut(MarketEvent)) -> Result<(), SubscriberError> {
+        while let Some(event) = self.next_event()? {
+            callback(event);
+        }
+        Ok(())
+    }
+
+    pub fn into_channel(mut self) -> (StopHandle, mpsc::Receiver<MarketEvent>) {
+        let stop = self.stop_handle();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            while let Ok(Some(event)) = self.next_event() {
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+        (stop, rx)
+    }
+}
+
+impl Drop for QuoteSubscriber {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        for line in &mut self.lines {
+            line.close();
+        }
+        for line in &mut self.lines {
+            line.join();
+        }
+        if let Some(handle) = self.pinger.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+pub(crate) fn check_reply(reply: Option<String>) -> Result<StreamAccepted, SubscriberError> {
+    match reply {
+        Some(reply) if reply.starts_with("ERR") => Err(SubscriberError::Rejected(reply)),
+        Some(reply) => parse_accepted(&reply).map_err(|_| SubscriberError::InvalidReply(reply)),
+        None => Err(SubscriberError::NoResponse),
+    }
+}
+
+fn open_line(
+    server: usize,
+    addr: &str,
+    config: &SubscriberConfig,
+    command: &str,
+) -> Result<Line, SubscriberError> {
+    let (reply, control) = open_stream(addr, config.tls.as_ref(), config.auth.as_ref(), command)?;
+    let accepted = check_reply(reply)?;
+    let open = Arc::new(AtomicBool::new(true));
+    let watcher = spawn_watcher(control, open.clone());
+    Ok(Line {
+        server,
+        accepted,
+        open,
+        watcher: Some(watcher),
+        last_seen: Instant::now(),
+        heartbeats: false,
+    })
+}
+
+fn spawn_pinger(
+    socket: UdpSocket,
+    running: Arc<AtomicBool>,
+    tickets: Arc<Mutex<Vec<StreamAccepted>>>,
+    interval: Duration,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut since_ping = interval;
+        while running.load(Ordering::SeqCst) {
+            if since_ping >= interval {
+                let targets = tickets
+                    .lock()
+                    .map(|tickets| tickets.clone())
+                    .unwrap_or_default();
+                for accepted in &targets {
+                    let ping = accepted.ticket.ping();
+                    let _ = socket.send_to(ping.as_bytes(), accepted.endpoint);
+                }
+                if !targets.is_empty() {
+                    since_ping = Duration::ZERO;
+                }
+            }
+            thread::sleep(PING_POLL);
+            since_ping += PING_POLL;
+        }
+    })
+}
+
+fn spawn_watcher(control: BufReader<Control>, open: Arc<AtomicBool>) -> thread::JoinHandle<()> {
+    thread::spawn(move || watch_control(control, open))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, Write};
+    use std::net::TcpListener;
+
+    use quote_core::{QuoteGenerator, StreamTicket, parse_command};
+    use quote_server::auth::{Credentials, hash_token};
+    use quote_server::{QuoteServer, ServerConfig};
+
+    use super::*;
+
+    #[test]
+    fn subscribes_and_yields_quotes_until_shutdown() {
+        let server = QuoteServer::builder()
+            .bind("127.0.0.1:0")
+            .source(Box::new(QuoteGenerator::default()))
+            .start()
+            .unwrap();
+        let mut config = SubscriberConfig::new(&[&server.local_addr().to_string()], &["aapl"]);
+        config.reconnect = None;
+        let mut subscriber = QuoteSubscriber::connect(&config).unwrap();
+        let accepted = parse_accepted(subscriber.reply()).unwrap();
+        assert_eq!(accepted.endpoint.ip(), server.local_addr().ip());
+
+        let quote = subscriber.quotes().next().unwrap().unwrap();
+        assert_eq!(quote.ticker, "AAPL");
+
+        server.shutdown().unwrap();
+        let rest = subscriber.events().collect::<Result<Vec<_>, _>>().unwrap();
+        assert!(matches!(rest.last(), Some(MarketEvent::Control(_))));
+    }
+
+    #[test]
+    fn reconnects_after_server_restart() {
+        let server = QuoteServer::builder()
+            .bind("127.0.0.1:0")
+            .source(Box::new(QuoteGenerator::default()))
+            .start()
+            .unwrap();
+        let addr = server.local_addr().to_string();
+        let mut config = SubscriberConfig::new(&[&addr], &["TSLA"]);
+        config.reconnect = Some(Backoff {
+            initial: Duration::from_millis(50),
+            max: Duration::from_millis(200),
+        });
+        let mut subscriber = QuoteSubscriber::connect(&config).unwrap();
+        assert!(subscriber.quotes().next().unwrap().is_ok());
+
+        server.shutdown().unwrap();
+        let restarted = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            QuoteServer::builder()
+                .bind(&addr)
+                .source(Box::new(QuoteGenerator::default()))
+                .start()
+                .unwrap()
+        });
+        let mut events = subscriber.events();
+        assert!(events.any(|event| matches!(event, Ok(MarketEvent::Control(_)))));
+        let quote = events.find_map(|event| match event {
+            Ok(MarketEvent::Quote(quote)) => Some(quote),
+            _ => None,
+        });
+        assert_eq!(quote.unwrap().ticker, "TSLA");
+        drop(events);
+        restarted.join().unwrap().shutdown().unwrap();
+    }
+
+    fn sequenced_feed(
+        listener: TcpListener,
+        ready: Arc<std::sync::Barrier>,
+        sends: fn(u64) -> bool,
+        last: u64,
+    ) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            drop(listener);
+            let mut command = String::new();
+            BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut command)
+                .unwrap();
+            let request = parse_command(&command).unwrap();
+            let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let accepted = StreamAccepted {
+                ticket: StreamTicket {
+                    id: u64::from(udp.local_addr().unwrap().port()),
+                    token: "feed".to_string(),
+                },
+                endpoint: udp.local_addr().unwrap(),
+            };
+            let mut writer = stream;
+            writer
+                .write_all(format!("{accepted}\n").as_bytes())
+                .unwrap();
+            ready.wait();
+            let challenge = MarketEvent::Control(ControlEvent::challenge("n1"));
+            udp.send_to(&challenge.to_bytes(), request.addr).unwrap();
+            udp.set_nonblocking(true).unwrap();
+            let mut buf = [0u8; 256];
+            let mut proofs = Vec::new();
+            for seq in 1..=last {
+                while let Ok((n, _)) = udp.recv_from(&mut buf) {
+                    let payload = String::from_utf8_lossy(&buf[..n]).to_string();
+                    if payload.starts_with("PROOF") {
+                        proofs.push(payload);
+                    }
+                }
+                if sends(seq) {
+                    let quote = MarketEvent::Quote(StockQuote {
+                        ticker: "AAPL".to_string(),
+                        price: seq as f64,
+                        volume: 1,
+                        timestamp: seq,
+                        seq: Some(seq),
+                    });
+                    udp.send_to(&quote.to_bytes(), request.addr).unwrap();
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            assert_eq!(proofs, vec![accepted.ticket.proof("n1")]);
+        })
+    }
+
+    #[test]
+    fn fails_over_and_arbitrates_between_servers() {
+        let (a, b) = (
+            TcpListener::bind("127.0.0.1:0").unwrap(),
+            TcpListener::bind("127.0.0.1:0").unwrap(),
+        );
+        let addr_a = a.local_addr().unwrap().to_string();
+        let addr_b = b.local_addr().unwrap().to_string();
+        let ready = Arc::new(std::sync::Barrier::new(2));
+        let feed_a = sequenced_feed(a, ready.clone(), |seq| seq <= 40 && seq % 3 != 0, 100);
+        let feed_b = sequenced_feed(b, ready, |seq| seq % 3 != 1, 100);
+        let mut config = SubscriberConfig::new(&["127.0.0.1:1", &addr_a, &addr_b], &["AAPL"]);
+        config.arbitrate = true;
+        config.silence_timeout = Duration::from_millis(150);
+        let mut subscriber = QuoteSubscriber::connect(&config).unwrap();
+        assert_eq!(
+            subscriber.active_servers(),
+            vec![addr_a.as_str(), addr_b.as_str()]
+        );
+
+        let seqs: Vec<u64> = subscriber
+            .quotes()
+            .take(40)
+            .map(|quote| quote.unwrap().seq.unwrap())
+            .collect();
+        assert_eq!(seqs, (1..=40).collect::<Vec<_>>());
+
+        let rest: Vec<u64> = subscriber
+            .quotes()
+            .map(|quote| quote.unwrap().seq.unwrap())
+            .take_while(|seq| *seq < 99)
+            .collect();
+        assert!(rest.iter().all(|seq| seq % 3 != 1));
+        assert_eq!(subscriber.active_servers(), vec![addr_b.as_str()]);
+        drop(subscriber);
+        feed_a.join().unwrap();
+        feed_b.join().unwrap();
+    }
+
+    #[test]
+    fn fails_when_heartbeats_stop() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut command = String::new();
+            reader.read_line(&mut command).unwrap();
+            let request = parse_command(&command).unwrap();
+            let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let accepted = StreamAccepted {
+                ticket: StreamTicket {
+                    id: 1,
+                    token: "feed".to_string(),
+                },
+                endpoint: udp.local_addr().unwrap(),
+            };
+            let mut writer = stream;
+            writer
+                .write_all(format!("{accepted}\n").as_bytes())
+                .unwrap();
+            let heartbeat = MarketEvent::Control(ControlEvent::heartbeat(Default::default()));
+            udp.send_to(heartbeat.to_string().as_bytes(), request.addr)
+                .unwrap();
+            thread::sleep(Duration::from_secs(1));
+        });
+        let mut config = SubscriberConfig::new(&[&addr], &["AAPL"]);
+        config.reconnect = None;
+        config.heartbeat_timeout = Duration::from_millis(300);
+        let mut subscriber = QuoteSubscriber::connect(&config).unwrap();
+        assert!(matches!(
+            subscriber.next_event(),
+            Err(SubscriberError::Silent(window)) if window == config.heartbeat_timeout
+        ));
+        drop(subscriber);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(350),
+        };
+        assert_eq!(
+            backoff.next(Duration::from_millis(100)),
+            Duration::from_millis(200)
+        );
+        assert_eq!(
+            backoff.next(Duration::from_millis(200)),
+            Duration::from_millis(350)
+        );
+
+        let capped = Backoff {
+            initial: Duration::from_secs(5),
+            max: Duration::from_secs(1),
+        };
+        assert_eq!(capped.first(), Duration::from_secs(1));
+        assert_eq!(capped.next(capped.first()), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn authenticates_before_streaming() {
+        let credentials: Credentials = format!(
+            "[users.alice]\ntoken-sha256 = \"{}\"\n",
+            hash_token("s3cret")
+        )
+        .parse()
+        .unwrap();
+        let server = QuoteServer::builder()
+            .bind("127.0.0.1:0")
+            .config(ServerConfig {
+                credentials: Some(Arc::new(credentials)),
+                ..ServerConfig::default()
+            })
+            .start()
+            .unwrap();
+        let mut config = SubscriberConfig::new(&[&server.local_addr().to_string()], &["AAPL"]);
+        config.reconnect = None;
+        config.auth = Some(AuthRequest {
+            user: "alice".to_string(),
+            token: "guess".to_string(),
+        });
+        assert!(matches!(
+            QuoteSubscriber::connect(&config),
+            Err(SubscriberError::Rejected(reply)) if reply == "ERR AUTH_FAILED"
+        ));
+
+        config.auth = Some(AuthRequest {
+            user: "alice".to_string(),
+            token: "s3cret".to_string(),
+        });
+        let mut subscriber = QuoteSubscriber::connect(&config).unwrap();
+        assert_eq!(subscriber.quotes().next().unwrap().unwrap().ticker, "AAPL");
+        assert_eq!(server.active_streams()[0].user.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn rejects_empty_ticker_list() {
+        let config = SubscriberConfig::new(&["127.0.0.1:1"], &[]);
+        assert!(matches!(
+            QuoteSubscriber::connect(&config),
+            Err(SubscriberError::InvalidRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,57 @@
+pub use quote_core::tls::client_config;
+
+pub type Control = quote_core::tls::Control<rustls::ClientConnection>;
+
+#[cfg(test)]
+mod tests {
+    use quote_core::test_support::write_pki;
+    use quote_server::{QuoteServer, ServerConfig};
+
+    use super::*;
+    use crate::{QuoteSubscriber, SubscriberConfig};
+
+    #[test]
+    fn streams_over_tls_with_client_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        write_pki(dir.path());
+        let tls = quote_server::tls::server_config(
+            &path("server.pem"),
+            &path("server.key"),
+            Some(&path("ca.pem")),
+        )
+        .unwrap();
+        let server = QuoteServer::builder()
+            .bind("127.0.0.1:0")
+            .config(ServerConfig {
+                tls: Some(tls),
+                ..ServerConfig::default()
+            })
+            .start()
+            .unwrap();
+        let mut config = SubscriberConfig::new(&[&server.local_addr().to_string()], &["AAPL"]);
+        config.reconnect = None;
+        config.tls = Some(client_config(&path("ca.pem"), None).unwrap());
+        assert!(QuoteSubscriber::connect(&config).is_err());
+
+        let identity = Some((path("client.pem"), path("client.key")));
+        let identity = identity
+            .as_ref()
+            .map(|(cert, key)| (cert.as_path(), key.as_path()));
+        config.tls = Some(client_config(&path("ca.pem"), identity).unwrap());
+        let mut subscriber = QuoteSubscriber::connect(&config).unwrap();
+        assert_eq!(subscriber.quotes().next().unwrap().unwrap().ticker, "AAPL");
+
+        #[cfg(feature = "async")]
+        {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            let stream = runtime
+                .block_on(crate::QuoteStream::connect(&config))
+                .unwrap();
+            assert!(quote_core::parse_accepted(stream.reply()).is_ok());
+        }
+    }
+}
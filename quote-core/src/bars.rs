@@ -0,0 +1,169 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::quote::StockQuote;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bar {
+    pub ticker: String,
+    pub interval_ms: u64,
+    pub start: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: u64,
+    pub complete: bool,
+}
+
+impl Bar {
+    fn open_at(quote: &StockQuote, interval_ms: u64) -> Self {
+        Bar {
+            ticker: quote.ticker.clone(),
+            interval_ms,
+            start: quote.timestamp - quote.timestamp % interval_ms,
+            open: quote.price,
+            high: quote.price,
+            low: quote.price,
+            close: quote.price,
+            volume: quote.volume as u64,
+            complete: false,
+        }
+    }
+
+    fn end(&self) -> u64 {
+        self.start + self.interval_ms
+    }
+
+    fn add(&mut self, quote: &StockQuote) {
+        self.high = self.high.max(quote.price);
+        self.low = self.low.min(quote.price);
+        self.close = quote.price;
+        self.volume += quote.volume as u64;
+    }
+}
+
+pub struct BarAggregator {
+    interval_ms: u64,
+    watermark: u64,
+    bars: HashMap<String, Bar>,
+    updated: HashSet<String>,
+}
+
+impl BarAggregator {
+    pub fn new(interval: Duration) -> Self {
+        BarAggregator {
+            interval_ms: (interval.as_millis() as u64).max(1),
+            watermark: 0,
+            bars: HashMap::new(),
+            updated: HashSet::new(),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms)
+    }
+
+    pub fn in_progress(&self, ticker: &str) -> Option<&Bar> {
+        self.bars.get(ticker)
+    }
+
+    pub fn update(&mut self, quote: &StockQuote) -> Vec<Bar> {
+        let completed = self.advance(quote.timestamp);
+        self.updated.insert(quote.ticker.clone());
+        match self.bars.get_mut(&quote.ticker) {
+            Some(bar) => bar.add(quote),
+            None => {
+                let bar = Bar::open_at(quote, self.interval_ms);
+                self.bars.insert(quote.ticker.clone(), bar);
+            }
+        }
+        completed
+    }
+
+    pub fn drain_updated(&mut self) -> Vec<Bar> {
+        let mut bars = self
+            .updated
+            .drain()
+            .filter_map(|ticker| self.bars.get(&ticker).cloned())
+            .collect::<Vec<_>>();
+        bars.sort_by(|a, b| a.ticker.cmp(&b.ticker));
+        bars
+    }
+
+    pub fn advance(&mut self, now: u64) -> Vec<Bar> {
+        self.watermark = self.watermark.max(now);
+        let expired = self
+            .bars
+            .iter()
+            .filter(|(_, bar)| bar.end() <= self.watermark)
+            .map(|(ticker, _)| ticker.clone())
+            .collect::<Vec<_>>();
+        let mut completed = Vec::with_capacity(expired.len());
+        for ticker in expired {
+            if let Some(mut bar) = self.bars.remove(&ticker) {
+                bar.complete = true;
+                completed.push(bar);
+            }
+        }
+        completed.sort_by_key(|bar| bar.start);
+        completed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(ticker: &str, price: f64, volume: u32, timestamp: u64) -> StockQuote {
+        StockQuote {
+            ticker: ticker.to_string(),
+            price,
+            volume,
+            timestamp,
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn builds_ohlcv_bars() {
+        let mut aggregator = BarAggregator::new(Duration::from_secs(1));
+        assert!(aggregator.update(&quote("AAPL", 10.0, 1, 1_000)).is_empty());
+        assert!(aggregator.update(&quote("AAPL", 12.0, 2, 1_200)).is_empty());
+        assert!(aggregator.update(&quote("AAPL", 9.0, 3, 1_900)).is_empty());
+        assert_eq!(aggregator.in_progress("AAPL").unwrap().close, 9.0);
+        let done = aggregator.update(&quote("AAPL", 11.0, 4, 2_100));
+        assert_eq!(
+            done,
+            vec![Bar {
+                ticker: "AAPL".to_string(),
+                interval_ms: 1_000,
+                start: 1_000,
+                open: 10.0,
+                high: 12.0,
+                low: 9.0,
+                close: 9.0,
+                volume: 6,
+                complete: true,
+            }]
+        );
+        assert_eq!(aggregator.in_progress("AAPL").unwrap().start, 2_000);
+        let updated = aggregator.drain_updated();
+        assert_eq!(updated.len(), 1);
+        assert!(!updated[0].complete);
+        assert!(aggregator.drain_updated().is_empty());
+    }
+
+    #[test]
+    fn closes_quiet_tickers_by_event_time() {
+        let mut aggregator = BarAggregator::new(Duration::from_secs(60));
+        aggregator.update(&quote("TSLA", 100.0, 1, 10_000));
+        let done = aggregator.update(&quote("AAPL", 10.0, 1, 61_000));
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].ticker, "TSLA");
+        assert!(aggregator.in_progress("TSLA").is_none());
+        assert_eq!(aggregator.advance(120_000).len(), 1);
+    }
+}
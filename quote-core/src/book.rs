@@ -0,0 +1,285 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+const TICK_SIZE: f64 = 0.05;
+const PRICE_EPSILON: f64 = 1e-6;
+const MIN_LEVEL_SIZE: u32 = 100;
+const MAX_LEVEL_SIZE: u32 = 5000;
+const CHURN_PER_TRADE: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BookLevel {
+    pub price: f64,
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookSnapshot {
+    pub ticker: String,
+    pub seq: u64,
+    pub bids: Vec<BookLevel>,
+    pub asks: Vec<BookLevel>,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookUpdate {
+    pub ticker: String,
+    pub seq: u64,
+    pub side: Side,
+    pub price: f64,
+    pub size: u32,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBook {
+    ticker: String,
+    depth: usize,
+    seq: u64,
+    bids: Vec<BookLevel>,
+    asks: Vec<BookLevel>,
+}
+
+impl OrderBook {
+    pub fn new(ticker: &str, depth: usize) -> Self {
+        OrderBook {
+            ticker: ticker.to_string(),
+            depth,
+            seq: 0,
+            bids: Vec::new(),
+            asks: Vec::new(),
+        }
+    }
+
+    pub fn from_snapshot(snapshot: &BookSnapshot) -> Self {
+        OrderBook {
+            ticker: snapshot.ticker.clone(),
+            depth: snapshot.bids.len().max(snapshot.asks.len()),
+            seq: snapshot.seq,
+            bids: snapshot.bids.clone(),
+            asks: snapshot.asks.clone(),
+        }
+    }
+
+    pub fn ticker(&self) -> &str {
+        &self.ticker
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn bids(&self) -> &[BookLevel] {
+        &self.bids
+    }
+
+    pub fn asks(&self) -> &[BookLevel] {
+        &self.asks
+    }
+
+    pub fn best_bid(&self) -> Option<BookLevel> {
+        self.bids.first().copied()
+    }
+
+    pub fn best_ask(&self) -> Option<BookLevel> {
+        self.asks.first().copied()
+    }
+
+    pub fn snapshot(&self, timestamp: u64) -> BookSnapshot {
+        BookSnapshot {
+            ticker: self.ticker.clone(),
+            seq: self.seq,
+            bids: self.bids.clone(),
+            asks: self.asks.clone(),
+            timestamp,
+        }
+    }
+
+    pub fn apply(&mut self, update: &BookUpdate) -> bool {
+        if update.seq != self.seq + 1 {
+            return false;
+        }
+        self.seq = update.seq;
+        let levels = match update.side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+        set_level(levels, update.side, update.price, update.size);
+        true
+    }
+
+    pub fn evolve<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        trade_price: f64,
+        timestamp: u64,
+    ) -> Vec<BookUpdate> {
+        let reach = TICK_SIZE * (self.depth as f64) * 2.0;
+        let mut changes = Vec::new();
+        for level in &self.asks {
+            if level.price <= trade_price + PRICE_EPSILON || level.price > trade_price + reach {
+                changes.push((Side::Ask, level.price, 0));
+            }
+        }
+        for level in &self.bids {
+            if level.price >= trade_price - PRICE_EPSILON || level.price < trade_price - reach {
+                changes.push((Side::Bid, level.price, 0));
+            }
+        }
+        for _ in 0..CHURN_PER_TRADE {
+            let side = if rng.gen_bool(0.5) {
+                Side::Bid
+            } else {
+                Side::Ask
+            };
+            let levels = match side {
+                Side::Bid => &self.bids,
+                Side::Ask => &self.asks,
+            };
+            if levels.is_empty() {
+                continue;
+            }
+            let level = levels[rng.gen_range(0..levels.len())];
+            if changes
+                .iter()
+                .any(|(s, p, _)| *s == side && same_price(*p, level.price))
+            {
+                continue;
+            }
+            let size = if rng.gen_bool(0.2) {
+                0
+            } else {
+                rng.gen_range(MIN_LEVEL_SIZE..MAX_LEVEL_SIZE)
+            };
+            changes.push((side, level.price, size));
+        }
+        let mut updates = Vec::new();
+        for (side, price, size) in changes {
+            updates.push(self.record(side, price, size, timestamp));
+        }
+        let mut next_bid = self
+            .bids
+            .last()
+            .map(|l| l.price - TICK_SIZE)
+            .unwrap_or_else(|| round_tick(trade_price) - TICK_SIZE);
+        while self.bids.len() < self.depth && next_bid > 0.0 {
+            let size = rng.gen_range(MIN_LEVEL_SIZE..MAX_LEVEL_SIZE);
+            updates.push(self.record(Side::Bid, next_bid, size, timestamp));
+            next_bid -= TICK_SIZE;
+        }
+        let mut next_ask = self
+            .asks
+            .last()
+            .map(|l| l.price + TICK_SIZE)
+            .unwrap_or_else(|| round_tick(trade_price) + TICK_SIZE);
+        while self.asks.len() < self.depth {
+            let size = rng.gen_range(MIN_LEVEL_SIZE..MAX_LEVEL_SIZE);
+            updates.push(self.record(Side::Ask, next_ask, size, timestamp));
+            next_ask += TICK_SIZE;
+        }
+        updates
+    }
+
+    fn record(&mut self, side: Side, price: f64, size: u32, timestamp: u64) -> BookUpdate {
+        let price = round_tick(price);
+        self.seq += 1;
+        let levels = match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+        set_level(levels, side, price, size);
+        BookUpdate {
+            ticker: self.ticker.clone(),
+            seq: self.seq,
+            side,
+            price,
+            size,
+            timestamp,
+        }
+    }
+}
+
+fn set_level(levels: &mut Vec<BookLevel>, side: Side, price: f64, size: u32) {
+    let position = levels.iter().position(|l| same_price(l.price, price));
+    match (position, size) {
+        (Some(index), 0) => {
+            levels.remove(index);
+        }
+        (Some(index), size) => levels[index].size = size,
+        (None, 0) => {}
+        (None, size) => {
+            let index = levels
+                .iter()
+                .position(|l| match side {
+                    Side::Bid => l.price < price,
+                    Side::Ask => l.price > price,
+                })
+                .unwrap_or(levels.len());
+            levels.insert(index, BookLevel { price, size });
+        }
+    }
+}
+
+fn same_price(a: f64, b: f64) -> bool {
+    (a - b).abs() < PRICE_EPSILON
+}
+
+fn round_tick(price: f64) -> f64 {
+    ((price / TICK_SIZE).round() * TICK_SIZE * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+
+    #[test]
+    fn evolves_sorted_uncrossed_book() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut book = OrderBook::new("AAPL", 5);
+        let mut price = 100.0;
+        for step in 0..200 {
+            price += rng.gen_range(-0.3..0.3);
+            book.evolve(&mut rng, price, step);
+            assert_eq!(book.bids().len(), 5);
+            assert_eq!(book.asks().len(), 5);
+            assert!(book.bids().windows(2).all(|w| w[0].price > w[1].price));
+            assert!(book.asks().windows(2).all(|w| w[0].price < w[1].price));
+            assert!(book.best_bid().unwrap().price < book.best_ask().unwrap().price);
+        }
+    }
+
+    #[test]
+    fn replica_tracks_book_from_snapshot_and_updates() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut book = OrderBook::new("MSFT", 3);
+        book.evolve(&mut rng, 50.0, 0);
+        let mut replica = OrderBook::from_snapshot(&book.snapshot(0));
+        for step in 1..50 {
+            for update in book.evolve(&mut rng, 50.0 + step as f64 * 0.1, step) {
+                assert!(replica.apply(&update));
+            }
+        }
+        assert_eq!(replica.bids(), book.bids());
+        assert_eq!(replica.asks(), book.asks());
+        let stale = BookUpdate {
+            ticker: "MSFT".to_string(),
+            seq: 1,
+            side: Side::Bid,
+            price: 1.0,
+            size: 1,
+            timestamp: 0,
+        };
+        assert!(!replica.apply(&stale));
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use crate::quote::StockQuote;
+
+#[derive(Default)]
+pub struct Conflator {
+    latest: HashMap<String, StockQuote>,
+    order: Vec<String>,
+    replaced: u64,
+}
+
+impl Conflator {
+    pub fn new() -> Self {
+        Conflator::default()
+    }
+
+    pub fn push(&mut self, quote: StockQuote) {
+        match self.latest.get_mut(&quote.ticker) {
+            Some(slot) => {
+                *slot = quote;
+                self.replaced += 1;
+            }
+            None => {
+                self.order.push(quote.ticker.clone());
+                self.latest.insert(quote.ticker.clone(), quote);
+            }
+        }
+    }
+
+    pub fn drain(&mut self) -> Vec<StockQuote> {
+        let mut quotes = Vec::with_capacity(self.order.len());
+        for ticker in self.order.drain(..) {
+            if let Some(quote) = self.latest.remove(&ticker) {
+                quotes.push(quote);
+            }
+        }
+        quotes
+    }
+
+    pub fn len(&self) -> usize {
+        self.latest.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_empty()
+    }
+
+    pub fn replaced(&self) -> u64 {
+        self.replaced
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(ticker: &str, price: f64) -> StockQuote {
+        StockQuote {
+            ticker: ticker.to_string(),
+            price,
+            volume: 1,
+            timestamp: 0,
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn keeps_latest_quote_per_ticker() {
+        let mut conflator = Conflator::new();
+        conflator.push(quote("TSLA", 1.0));
+        conflator.push(quote("AAPL", 2.0));
+        conflator.push(quote("TSLA", 3.0));
+        assert_eq!(conflator.len(), 2);
+        assert_eq!(conflator.replaced(), 1);
+        let drained = conflator.drain();
+        assert_eq!(drained, vec![quote("TSLA", 3.0), quote("AAPL", 2.0)]);
+        assert!(conflator.is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bars::Bar;
+use crate::book::{BookSnapshot, BookUpdate};
+use crate::quote::{StockQuote, now_millis};
+use crate::session::MarketSession;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TradingStatus {
+    Trading,
+    Halted,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusEvent {
+    pub ticker: String,
+    pub status: TradingStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionEvent {
+    pub session: MarketSession,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ControlKind {
+    Shutdown,
+    Heartbeat,
+    Challenge,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ControlEvent {
+    pub control: ControlKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub seqs: BTreeMap<String, u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+}
+
+impl ControlEvent {
+    pub fn shutdown(reason: &str) -> Self {
+        ControlEvent {
+            control: ControlKind::Shutdown,
+            reason: Some(reason.to_string()),
+            timestamp: now_millis(),
+            seqs: BTreeMap::new(),
+            nonce: None,
+        }
+    }
+
+    pub fn heartbeat(seqs: BTreeMap<String, u64>) -> Self {
+        ControlEvent {
+            control: ControlKind::Heartbeat,
+            reason: None,
+            timestamp: now_millis(),
+            seqs,
+            nonce: None,
+        }
+    }
+
+    pub fn challenge(nonce: &str) -> Self {
+        ControlEvent {
+            control: ControlKind::Challenge,
+            reason: None,
+            timestamp: now_millis(),
+            seqs: BTreeMap::new(),
+            nonce: Some(nonce.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MarketEvent {
+    Quote(StockQuote),
+    Status(StatusEvent),
+    Session(SessionEvent),
+    Book(BookSnapshot),
+    BookUpdate(BookUpdate),
+    Bar(Bar),
+    Control(ControlEvent),
+}
+
+impl MarketEvent {
+    pub fn ticker(&self) -> Option<&str> {
+        match self {
+            MarketEvent::Quote(quote) => Some(&quote.ticker),
+            MarketEvent::Status(status) => Some(&status.ticker),
+            MarketEvent::Session(_) | MarketEvent::Control(_) => None,
+            MarketEvent::Book(book) => Some(&book.ticker),
+            MarketEvent::BookUpdate(update) => Some(&update.ticker),
+            MarketEvent::Bar(bar) => Some(&bar.ticker),
+        }
+    }
+
+    pub fn is_depth(&self) -> bool {
+        matches!(self, MarketEvent::Book(_) | MarketEvent::BookUpdate(_))
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            MarketEvent::Quote(quote) => quote.timestamp,
+            MarketEvent::Status(status) => status.timestamp,
+            MarketEvent::Session(session) => session.timestamp,
+            MarketEvent::Book(book) => book.timestamp,
+            MarketEvent::BookUpdate(update) => update.timestamp,
+            MarketEvent::Bar(bar) => bar.start,
+            MarketEvent::Control(control) => control.timestamp,
+        }
+    }
+
+    pub fn from_string(s: &str) -> Option<Self> {
+        serde_json::from_str(s).ok()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+}
+
+impl From<StockQuote> for MarketEvent {
+    fn from(quote: StockQuote) -> Self {
+        MarketEvent::Quote(quote)
+    }
+}
+
+impl fmt::Display for MarketEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let encoded = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        write!(f, "{encoded}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_keep_plain_wire_format() {
+        let quote = StockQuote {
+            ticker: "AAPL".to_string(),
+            price: 1.5,
+            volume: 10,
+            timestamp: 7,
+            seq: None,
+        };
+        let event = MarketEvent::from(quote.clone());
+        assert_eq!(event.to_string(), quote.to_string());
+        assert_eq!(MarketEvent::from_string(&quote.to_string()), Some(event));
+    }
+
+    #[test]
+    fn decodes_status_events() {
+        let raw = r#"{"ticker":"TSLA","status":"HALTED","reason":"news","timestamp":5}"#;
+        let event = MarketEvent::from_string(raw).unwrap();
+        assert_eq!(
+            event,
+            MarketEvent::Status(StatusEvent {
+                ticker: "TSLA".to_string(),
+                status: TradingStatus::Halted,
+                reason: Some("news".to_string()),
+                timestamp: 5,
+            })
+        );
+        assert_eq!(event.to_string(), raw);
+    }
+
+    #[test]
+    fn decodes_session_events() {
+        let raw = r#"{"session":"PRE_MARKET","timestamp":9}"#;
+        let event = MarketEvent::from_string(raw).unwrap();
+        assert_eq!(event.ticker(), None);
+        assert!(matches!(
+            event,
+            MarketEvent::Session(SessionEvent {
+                session: MarketSession::PreMarket,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn decodes_depth_events() {
+        let update =
+            r#"{"ticker":"AAPL","seq":3,"side":"bid","price":99.95,"size":0,"timestamp":1}"#;
+        assert!(matches!(
+            MarketEvent::from_string(update),
+            Some(MarketEvent::BookUpdate(_))
+        ));
+        let snapshot = r#"{"ticker":"AAPL","seq":3,"bids":[{"price":99.95,"size":100}],"asks":[],"timestamp":1}"#;
+        let event = MarketEvent::from_string(snapshot).unwrap();
+        assert!(matches!(event, MarketEvent::Book(_)));
+        assert!(event.is_depth());
+    }
+
+    #[test]
+    fn decodes_control_events() {
+        let raw = r#"{"control":"SHUTDOWN","reason":"server shutdown","timestamp":3}"#;
+        let event = MarketEvent::from_string(raw).unwrap();
+        assert_eq!(event.ticker(), None);
+        assert!(matches!(
+            event,
+            MarketEvent::Control(ControlEvent {
+                control: ControlKind::Shutdown,
+                ..
+            })
+        ));
+        assert_eq!(event.to_string(), raw);
+
+        let raw = r#"{"control":"HEARTBEAT","timestamp":4,"seqs":{"AAPL":17,"MSFT":3}}"#;
+        let event = MarketEvent::from_string(raw).unwrap();
+        assert!(matches!(
+            &event,
+            MarketEvent::Control(ControlEvent {
+                control: ControlKind::Heartbeat,
+                seqs,
+                ..
+            }) if seqs.get("AAPL") == Some(&17) && seqs.len() == 2
+        ));
+        assert_eq!(event.to_string(), raw);
+
+        let raw = r#"{"control":"CHALLENGE","timestamp":5,"nonce":"9f86d081"}"#;
+        let event = MarketEvent::from_string(raw).unwrap();
+        assert!(matches!(
+            &event,
+            MarketEvent::Control(ControlEvent {
+                control: ControlKind::Challenge,
+                nonce: Some(nonce),
+                ..
+            }) if nonce == "9f86d081"
+        ));
+        assert_eq!(event.to_string(), raw);
+    }
+}
@@ -1,10 +1,15 @@
-use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::time::Duration;
 
-use rand::Rng;
-use rand::rngs::ThreadRng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-use crate::quote::StockQuote;
+use crate::book::OrderBook;
+use crate::event::{MarketEvent, SessionEvent, StatusEvent, TradingStatus};
+use crate::quote::{StockQuote, now_millis};
+use crate::scenario::{Scenario, ScenarioAction, ScenarioEngine, ScheduledAction};
+use crate::session::{MarketSession, SessionSchedule};
 
 const START_PRICE_MIN: f64 = 50.0;
 const START_PRICE_MAX: f64 = 500.0;
@@ -15,6 +20,10 @@ const POPULAR_VOLUME_BASE: u32 = 1000;
 const POPULAR_VOLUME_SPREAD: f64 = 5000.0;
 const REGULAR_VOLUME_BASE: u32 = 100;
 const REGULAR_VOLUME_SPREAD: f64 = 1000.0;
+const POPULAR_RATE_PER_SEC: f64 = 20.0;
+const REGULAR_RATE_PER_SEC: f64 = 3.0;
+const POPULAR_TICKERS: &[&str] = &["AAPL", "MSFT", "TSLA"];
+const BOOK_SNAPSHOT_INTERVAL_MS: u64 = 1000;
 
 pub const DEFAULT_TICKERS: &[&str] = &[
     "AAPL", "MSFT", "GOOGL", "AMZN", "NVDA", "META", "TSLA", "JPM", "JNJ", "V", "PG", "UNH", "HD",
@@ -28,56 +37,376 @@ pub const DEFAULT_TICKERS: &[&str] = &[
     "SLB", "HUM", "BSX", "NOC", "EW",
 ];
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpdateRate {
+    Fixed(Duration),
+    Poisson { per_sec: f64 },
+}
+
+impl UpdateRate {
+    fn next_delay<R: Rng>(&self, rng: &mut R) -> u64 {
+        match *self {
+            UpdateRate::Fixed(interval) => (interval.as_millis() as u64).max(1),
+            UpdateRate::Poisson { per_sec } => {
+                let uniform: f64 = rng.gen_range(f64::EPSILON..1.0);
+                let secs = -uniform.ln() / per_sec.max(f64::EPSILON);
+                ((secs * 1000.0).round() as u64).max(1)
+            }
+        }
+    }
+}
+
+struct BookState {
+    book: OrderBook,
+    last_snapshot: Option<u64>,
+}
+
+struct Instrument {
+    price: f64,
+    rate: UpdateRate,
+    next_due: u64,
+    halted: bool,
+    volatility: f64,
+    volatility_until: u64,
+}
+
 pub struct QuoteGenerator {
-    prices: HashMap<String, f64>,
-    rng: ThreadRng,
+    instruments: HashMap<String, Instrument>,
+    schedule: BinaryHeap<Reverse<(u64, String)>>,
+    scenario: Option<ScenarioEngine>,
+    sessions: Option<SessionSchedule>,
+    session: Option<MarketSession>,
+    books: HashMap<String, BookState>,
+    rng: StdRng,
 }
 
 impl QuoteGenerator {
     pub fn new<T: IntoIterator<Item = String>>(tickers: T) -> Self {
-        let mut rng = rand::thread_rng();
-        let mut prices = HashMap::new();
+        let mut rng = StdRng::from_entropy();
+        let mut instruments = HashMap::new();
+        let mut schedule = BinaryHeap::new();
+        let now = now_millis();
         for ticker in tickers {
+            let ticker = ticker.to_uppercase();
             let price = rng.gen_range(START_PRICE_MIN..START_PRICE_MAX);
-            prices.insert(ticker.to_uppercase(), price);
+            let rate = default_rate(&ticker);
+            let next_due = now.saturating_add(rate.next_delay(&mut rng));
+            schedule.push(Reverse((next_due, ticker.clone())));
+            instruments.insert(
+                ticker,
+                Instrument {
+                    price,
+                    rate,
+                    next_due,
+                    halted: false,
+                    volatility: 1.0,
+                    volatility_until: 0,
+                },
+            );
+        }
+        QuoteGenerator {
+            instruments,
+            schedule,
+            scenario: None,
+            sessions: None,
+            session: None,
+            books: HashMap::new(),
+            rng,
         }
-        QuoteGenerator { prices, rng }
     }
 
-    pub fn default() -> Self {
-        let tickers = DEFAULT_TICKERS
-            .iter()
-            .map(|t| t.to_string())
-            .collect::<Vec<_>>();
-        QuoteGenerator::new(tickers)
+    pub fn set_depth(&mut self, levels: usize) {
+        self.books = if levels == 0 {
+            HashMap::new()
+        } else {
+            self.instruments
+                .keys()
+                .map(|ticker| {
+                    let state = BookState {
+                        book: OrderBook::new(ticker, levels),
+                        last_snapshot: None,
+                    };
+                    (ticker.clone(), state)
+                })
+                .collect()
+        };
+    }
+
+    pub fn set_sessions(&mut self, sessions: SessionSchedule) {
+        self.sessions = Some(sessions);
+        self.session = None;
+    }
+
+    pub fn session(&self) -> Option<MarketSession> {
+        self.session
+    }
+
+    pub fn set_scenario(&mut self, scenario: Scenario) {
+        self.scenario = Some(ScenarioEngine::new(scenario, now_millis()));
+    }
+
+    pub fn halt(&mut self, ticker: &str, reason: Option<String>) -> Option<StatusEvent> {
+        self.set_halted(ticker, true, reason, now_millis())
+    }
+
+    pub fn resume(&mut self, ticker: &str) -> Option<StatusEvent> {
+        self.set_halted(ticker, false, None, now_millis())
+    }
+
+    fn set_halted(
+        &mut self,
+        ticker: &str,
+        halted: bool,
+        reason: Option<String>,
+        timestamp: u64,
+    ) -> Option<StatusEvent> {
+        let ticker = ticker.to_uppercase();
+        let instrument = self.instruments.get_mut(&ticker)?;
+        if instrument.halted == halted {
+            return None;
+        }
+        instrument.halted = halted;
+        let status = if halted {
+            TradingStatus::Halted
+        } else {
+            TradingStatus::Trading
+        };
+        Some(StatusEvent {
+            ticker,
+            status,
+            reason,
+            timestamp,
+        })
+    }
+
+    pub fn set_rate(&mut self, ticker: &str, rate: UpdateRate) -> bool {
+        let ticker = ticker.to_uppercase();
+        let Some(instrument) = self.instruments.get_mut(&ticker) else {
+            return false;
+        };
+        instrument.rate = rate;
+        instrument.next_due = now_millis().saturating_add(rate.next_delay(&mut self.rng));
+        self.schedule.push(Reverse((instrument.next_due, ticker)));
+        true
+    }
+
+    pub fn next_due(&mut self) -> Option<u64> {
+        while let Some(Reverse((due, ticker))) = self.schedule.peek() {
+            let current = self
+                .instruments
+                .get(ticker)
+                .is_some_and(|instrument| instrument.next_due == *due);
+            if current {
+                return Some(*due);
+            }
+            self.schedule.pop();
+        }
+        None
     }
 
     pub fn generate_all(&mut self) -> Vec<StockQuote> {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis() as u64;
-        let mut quotes = Vec::with_capacity(self.prices.len());
-        for (ticker, price) in self.prices.iter_mut() {
-            let drift = self.rng.gen_range(-DRIFT_RANGE..DRIFT_RANGE);
-            let updated = (*price + drift).max(MIN_PRICE);
-            *price = updated;
-            let volume = match ticker.as_str() {
-                "AAPL" | "MSFT" | "TSLA" => {
-                    POPULAR_VOLUME_BASE + self.rng.gen_range(0.0..POPULAR_VOLUME_SPREAD) as u32
+        let now = now_millis();
+        let tickers = self.instruments.keys().cloned().collect::<Vec<_>>();
+        tickers
+            .into_iter()
+            .map(|ticker| self.next_quote(ticker, now))
+            .collect()
+    }
+
+    pub fn poll(&mut self, now: u64) -> Vec<MarketEvent> {
+        let actions = match self.scenario.as_mut() {
+            Some(engine) => engine.due(now),
+            None => Vec::new(),
+        };
+        let mut events = Vec::new();
+        events.extend(self.update_session(now).map(MarketEvent::Session));
+        for action in actions {
+            events.extend(self.apply(action, now));
+        }
+        events.extend(self.generate_due(now).into_iter().map(MarketEvent::from));
+        let depth = self.update_books(&events);
+        events.extend(depth);
+        events
+    }
+
+    fn update_books(&mut self, events: &[MarketEvent]) -> Vec<MarketEvent> {
+        let mut depth = Vec::new();
+        for event in events {
+            let MarketEvent::Quote(quote) = event else {
+                continue;
+            };
+            let Some(state) = self.books.get_mut(&quote.ticker) else {
+                continue;
+            };
+            let updates = state
+                .book
+                .evolve(&mut self.rng, quote.price, quote.timestamp);
+            let snapshot_due = state
+                .last_snapshot
+                .is_none_or(|last| quote.timestamp >= last + BOOK_SNAPSHOT_INTERVAL_MS);
+            if snapshot_due {
+                state.last_snapshot = Some(quote.timestamp);
+                depth.push(MarketEvent::Book(state.book.snapshot(quote.timestamp)));
+            } else {
+                depth.extend(updates.into_iter().map(MarketEvent::BookUpdate));
+            }
+        }
+        depth
+    }
+
+    fn update_session(&mut self, now: u64) -> Option<SessionEvent> {
+        let current = self.sessions.as_ref()?.session_at(now);
+        if self.session == Some(current) {
+            return None;
+        }
+        self.session = Some(current);
+        Some(SessionEvent {
+            session: current,
+            timestamp: now,
+        })
+    }
+
+    fn trading_allowed(&self) -> bool {
+        self.session.is_none_or(MarketSession::allows_trading)
+    }
+
+    fn apply(&mut self, scheduled: ScheduledAction, now: u64) -> Vec<MarketEvent> {
+        let tickers = if scheduled.tickers.is_empty() {
+            self.instruments.keys().cloned().collect::<Vec<_>>()
+        } else {
+            scheduled.tickers
+        };
+        let mut events = Vec::new();
+        for ticker in tickers {
+            match &scheduled.action {
+                ScenarioAction::Shock { percent } => {
+                    let Some(instrument) = self.instruments.get_mut(&ticker) else {
+                        continue;
+                    };
+                    instrument.price = (instrument.price * (1.0 + percent / 100.0)).max(MIN_PRICE);
+                    let (price, halted) = (instrument.price, instrument.halted);
+                    if !halted && self.trading_allowed() {
+                        events.push(MarketEvent::Quote(self.quote_at(ticker, price, now)));
+                    }
+                }
+                ScenarioAction::Halt { reason } => {
+                    events.extend(
+                        self.set_halted(&ticker, true, reason.clone(), now)
+                            .map(MarketEvent::Status),
+                    );
+                }
+                ScenarioAction::Resume => {
+                    events.extend(
+                        self.set_halted(&ticker, false, None, now)
+                            .map(MarketEvent::Status),
+                    );
+                }
+                ScenarioAction::Volatility {
+                    multiplier,
+                    duration,
+                } => {
+                    if let Some(instrument) = self.instruments.get_mut(&ticker) {
+                        instrument.volatility = *multiplier;
+                        instrument.volatility_until = now + duration.as_millis() as u64;
+                    }
+                }
+                ScenarioAction::FatFinger { percent } => {
+                    let Some(instrument) = self.instruments.get(&ticker) else {
+                        continue;
+                    };
+                    let (price, halted) = (instrument.price, instrument.halted);
+                    if !halted && self.trading_allowed() {
+                        let print = (price * (1.0 + percent / 100.0)).max(MIN_PRICE);
+                        events.push(MarketEvent::Quote(self.quote_at(ticker, print, now)));
+                    }
                 }
-                _ => REGULAR_VOLUME_BASE + self.rng.gen_range(0.0..REGULAR_VOLUME_SPREAD) as u32,
+            }
+        }
+        events
+    }
+
+    pub fn generate_due(&mut self, now: u64) -> Vec<StockQuote> {
+        let trading = self.trading_allowed();
+        let mut quotes = Vec::new();
+        while let Some(Reverse((due, _))) = self.schedule.peek() {
+            if *due > now {
+                break;
+            }
+            let Some(Reverse((due, ticker))) = self.schedule.pop() else {
+                break;
             };
-            let rounded = (updated * ROUND_FACTOR).round() / ROUND_FACTOR;
-            quotes.push(StockQuote {
-                ticker: ticker.clone(),
-                price: rounded,
-                volume,
-                timestamp: now,
-            });
+            let Some(instrument) = self.instruments.get_mut(&ticker) else {
+                continue;
+            };
+            if instrument.next_due != due {
+                continue;
+            }
+            instrument.next_due = due.saturating_add(instrument.rate.next_delay(&mut self.rng));
+            self.schedule
+                .push(Reverse((instrument.next_due, ticker.clone())));
+            if instrument.halted || !trading {
+                continue;
+            }
+            quotes.push(self.next_quote(ticker, due));
         }
         quotes
     }
+
+    fn next_quote(&mut self, ticker: String, timestamp: u64) -> StockQuote {
+        let drift = self.rng.gen_range(-DRIFT_RANGE..DRIFT_RANGE);
+        let price = match self.instruments.get_mut(&ticker) {
+            Some(instrument) => {
+                let scale = if timestamp < instrument.volatility_until {
+                    instrument.volatility
+                } else {
+                    1.0
+                };
+                instrument.price = (instrument.price + drift * scale).max(MIN_PRICE);
+                instrument.price
+            }
+            None => MIN_PRICE,
+        };
+        self.quote_at(ticker, price, timestamp)
+    }
+
+    fn quote_at(&mut self, ticker: String, price: f64, timestamp: u64) -> StockQuote {
+        let volume = if is_popular(&ticker) {
+            POPULAR_VOLUME_BASE + self.rng.gen_range(0.0..POPULAR_VOLUME_SPREAD) as u32
+        } else {
+            REGULAR_VOLUME_BASE + self.rng.gen_range(0.0..REGULAR_VOLUME_SPREAD) as u32
+        };
+        let rounded = (price * ROUND_FACTOR).round() / ROUND_FACTOR;
+        StockQuote {
+            ticker,
+            price: rounded,
+            volume,
+            timestamp,
+            seq: None,
+        }
+    }
+}
+
+impl Default for QuoteGenerator {
+    fn default() -> Self {
+        let tickers = DEFAULT_TICKERS
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>();
+        QuoteGenerator::new(tickers)
+    }
+}
+
+fn is_popular(ticker: &str) -> bool {
+    POPULAR_TICKERS.contains(&ticker)
+}
+
+fn default_rate(ticker: &str) -> UpdateRate {
+    let per_sec = if is_popular(ticker) {
+        POPULAR_RATE_PER_SEC
+    } else {
+        REGULAR_RATE_PER_SEC
+    };
+    UpdateRate::Poisson { per_sec }
 }
 
 #[cfg(test)]
@@ -91,4 +420,121 @@ mod tests {
         assert!(!quotes.is_empty());
         assert!(quotes.iter().all(|q| q.price > 0.0 && q.volume > 0));
     }
+
+    #[test]
+    fn emits_each_ticker_at_its_own_rate() {
+        let mut generator = QuoteGenerator::new(vec!["AAPL".to_string(), "XYZ".to_string()]);
+        generator.set_rate("AAPL", UpdateRate::Fixed(Duration::from_millis(100)));
+        generator.set_rate("XYZ", UpdateRate::Fixed(Duration::from_millis(500)));
+        let expected = generator.instruments.values().map(|i| i.next_due).min();
+        assert_eq!(generator.next_due(), expected);
+        let start = now_millis();
+        let mut quotes = Vec::new();
+        for step in 0..=100 {
+            quotes.extend(generator.generate_due(start + step * 10));
+        }
+        let fast = quotes.iter().filter(|q| q.ticker == "AAPL").count();
+        let slow = quotes.iter().filter(|q| q.ticker == "XYZ").count();
+        assert!((9..=10).contains(&fast), "fast={fast}");
+        assert!((1..=2).contains(&slow), "slow={slow}");
+        assert!(quotes.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+    }
+
+    #[test]
+    fn poisson_rate_is_close_to_target() {
+        let mut generator = QuoteGenerator::new(vec!["AAPL".to_string()]);
+        generator.set_rate("AAPL", UpdateRate::Poisson { per_sec: 50.0 });
+        let start = now_millis();
+        let count = generator.generate_due(start + 20_000).len();
+        assert!((800..=1200).contains(&count), "count={count}");
+
+        for per_sec in [0.0, -5.0, f64::NAN] {
+            generator.set_rate("AAPL", UpdateRate::Poisson { per_sec });
+            assert!(generator.generate_due(now_millis() + 60_000).is_empty());
+        }
+    }
+
+    #[test]
+    fn scenario_halts_and_shocks_tickers() {
+        let mut generator = QuoteGenerator::new(vec!["AAPL".to_string(), "TSLA".to_string()]);
+        generator.set_rate("AAPL", UpdateRate::Fixed(Duration::from_millis(10)));
+        generator.set_rate("TSLA", UpdateRate::Fixed(Duration::from_millis(10)));
+        let scenario = Scenario::parse(
+            r#"{"events":[
+                {"at":"0ms","action":"halt","tickers":["TSLA"]},
+                {"at":"0ms","action":"shock","tickers":["AAPL"],"percent":-50}
+            ]}"#,
+        )
+        .unwrap();
+        let before = generator.instruments["AAPL"].price;
+        generator.set_scenario(scenario);
+        let events = generator.poll(now_millis() + 100);
+        assert!(events.iter().any(|e| matches!(
+            e,
+            MarketEvent::Status(StatusEvent {
+                status: TradingStatus::Halted,
+                ..
+            })
+        )));
+        assert!(
+            events
+                .iter()
+                .all(|e| !matches!(e, MarketEvent::Quote(q) if q.ticker == "TSLA"))
+        );
+        let shocked = events
+            .iter()
+            .find_map(|e| match e {
+                MarketEvent::Quote(q) if q.ticker == "AAPL" => Some(q.price),
+                _ => None,
+            })
+            .unwrap();
+        assert!(shocked <= (before * 0.5).max(MIN_PRICE) + 0.01);
+        assert!(generator.resume("TSLA").is_some());
+        assert!(generator.resume("TSLA").is_none());
+    }
+
+    #[test]
+    fn suppresses_trades_outside_sessions() {
+        let mut generator = QuoteGenerator::new(vec!["AAPL".to_string()]);
+        generator.set_rate("AAPL", UpdateRate::Fixed(Duration::from_millis(10)));
+        let closed_all_day = SessionSchedule {
+            pre_market: 0,
+            open: 0,
+            close: 0,
+            after_hours: 0,
+            end: 0,
+            ..SessionSchedule::us_equities()
+        };
+        generator.set_sessions(closed_all_day);
+        let events = generator.poll(now_millis() + 100);
+        assert_eq!(
+            events,
+            vec![MarketEvent::Session(SessionEvent {
+                session: MarketSession::Closed,
+                timestamp: events[0].timestamp(),
+            })]
+        );
+        assert_eq!(generator.session(), Some(MarketSession::Closed));
+        assert!(generator.poll(now_millis() + 200).is_empty());
+    }
+
+    #[test]
+    fn publishes_snapshot_then_book_updates() {
+        let mut generator = QuoteGenerator::new(vec!["AAPL".to_string()]);
+        generator.set_rate("AAPL", UpdateRate::Fixed(Duration::from_millis(10)));
+        generator.set_depth(3);
+        let events = generator.poll(now_millis() + 100);
+        let depth = events.iter().filter(|e| e.is_depth()).collect::<Vec<_>>();
+        let MarketEvent::Book(snapshot) = depth[0] else {
+            panic!("expected snapshot first, got {:?}", depth[0]);
+        };
+        assert_eq!(snapshot.bids.len(), 3);
+        let mut replica = OrderBook::from_snapshot(snapshot);
+        for event in &depth[1..] {
+            if let MarketEvent::BookUpdate(update) = event {
+                assert!(replica.apply(update));
+            }
+        }
+        assert!(replica.best_bid().unwrap().price < replica.best_ask().unwrap().price);
+    }
 }
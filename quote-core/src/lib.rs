@@ -1,7 +1,33 @@
+pub mod bars;
+pub mod book;
+pub mod conflate;
+pub mod event;
 pub mod generator;
 pub mod protocol;
 pub mod quote;
+pub mod replay;
+pub mod scenario;
+pub mod sequence;
+pub mod session;
+pub mod source;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+#[cfg(feature = "tls")]
+pub mod tls;
 
-pub use generator::QuoteGenerator;
-pub use protocol::{ProtocolError, StreamRequest, parse_command};
-pub use quote::StockQuote;
+pub use bars::{Bar, BarAggregator};
+pub use book::{BookLevel, BookSnapshot, BookUpdate, OrderBook, Side};
+pub use conflate::Conflator;
+pub use event::{ControlEvent, ControlKind, MarketEvent, SessionEvent, StatusEvent, TradingStatus};
+pub use generator::{QuoteGenerator, UpdateRate};
+pub use protocol::{
+    AuthRequest, OverflowPolicy, ProtocolError, StreamAccepted, StreamOptions, StreamRequest,
+    StreamTicket, parse_accepted, parse_auth, parse_command, parse_duration, parse_ping,
+    parse_pong, parse_proof,
+};
+pub use quote::{StockQuote, now_millis};
+pub use replay::{ReplayError, ReplaySource, ReplaySpeed};
+pub use scenario::{Scenario, ScenarioEngine, ScenarioError};
+pub use sequence::Sequencer;
+pub use session::{MarketSession, SessionSchedule};
+pub use source::{QuoteSource, SourceError, UpstreamConfig, UpstreamSource};
@@ -1,10 +1,56 @@
 use std::fmt;
 use std::net::{SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    #[default]
+    DropOldest,
+    DropNewest,
+    Conflate,
+    Disconnect,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "drop-oldest" => Ok(OverflowPolicy::DropOldest),
+            "drop-newest" => Ok(OverflowPolicy::DropNewest),
+            "conflate" => Ok(OverflowPolicy::Conflate),
+            "disconnect" => Ok(OverflowPolicy::Disconnect),
+            _ => Err(format!("invalid overflow policy: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OverflowPolicy::DropOldest => "drop-oldest",
+            OverflowPolicy::DropNewest => "drop-newest",
+            OverflowPolicy::Conflate => "conflate",
+            OverflowPolicy::Disconnect => "disconnect",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamOptions {
+    pub depth: bool,
+    pub bars: Option<Duration>,
+    pub conflate: Option<Duration>,
+    pub overflow: Option<OverflowPolicy>,
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StreamRequest {
     pub addr: SocketAddr,
     pub tickers: Vec<String>,
+    pub options: StreamOptions,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -13,6 +59,8 @@ pub enum ProtocolError {
     InvalidScheme,
     InvalidAddress,
     EmptyTickers,
+    InvalidOption,
+    ConflictingOptions,
 }
 
 impl fmt::Display for ProtocolError {
@@ -22,6 +70,8 @@ impl fmt::Display for ProtocolError {
             ProtocolError::InvalidScheme => "invalid scheme",
             ProtocolError::InvalidAddress => "invalid address",
             ProtocolError::EmptyTickers => "empty tickers",
+            ProtocolError::InvalidOption => "invalid option",
+            ProtocolError::ConflictingOptions => "conflicting options",
         };
         write!(f, "{msg}")
     }
@@ -30,7 +80,7 @@ impl fmt::Display for ProtocolError {
 pub fn parse_command(input: &str) -> Result<StreamRequest, ProtocolError> {
     let trimmed = input.trim();
     let parts: Vec<&str> = trimmed.split_whitespace().collect();
-    if parts.len() != 3 {
+    if parts.len() < 3 {
         return Err(ProtocolError::InvalidFormat);
     }
     if !parts[0].eq_ignore_ascii_case("STREAM") {
@@ -53,7 +103,186 @@ pub fn parse_command(input: &str) -> Result<StreamRequest, ProtocolError> {
     if tickers.is_empty() {
         return Err(ProtocolError::EmptyTickers);
     }
-    Ok(StreamRequest { addr, tickers })
+    let options = parse_options(&parts[3..])?;
+    Ok(StreamRequest {
+        addr,
+        tickers,
+        options,
+    })
+}
+
+fn parse_options(tokens: &[&str]) -> Result<StreamOptions, ProtocolError> {
+    let mut options = StreamOptions::default();
+    let mut tokens = tokens.iter();
+    while let Some(token) = tokens.next() {
+        match token.to_ascii_uppercase().as_str() {
+            "DEPTH" => options.depth = true,
+            "BARS" => options.bars = Some(option_duration(tokens.next())?),
+            "CONFLATE" => options.conflate = Some(option_duration(tokens.next())?),
+            "POLICY" => {
+                let policy = tokens.next().ok_or(ProtocolError::InvalidOption)?;
+                options.overflow = Some(policy.parse().map_err(|_| ProtocolError::InvalidOption)?);
+            }
+            _ => return Err(ProtocolError::InvalidOption),
+        }
+    }
+    if options.bars.is_some() && options.conflate.is_some() {
+        return Err(ProtocolError::ConflictingOptions);
+    }
+    Ok(options)
+}
+
+fn option_duration(token: Option<&&str>) -> Result<Duration, ProtocolError> {
+    token
+        .and_then(|value| parse_duration(value))
+        .filter(|value| !value.is_zero())
+        .ok_or(ProtocolError::InvalidOption)
+}
+
+impl fmt::Display for StreamOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.depth {
+            write!(f, " DEPTH")?;
+        }
+        if let Some(interval) = self.bars {
+            write!(f, " BARS {}", format_duration(interval))?;
+        }
+        if let Some(interval) = self.conflate {
+            write!(f, " CONFLATE {}", format_duration(interval))?;
+        }
+        if let Some(policy) = self.overflow {
+            write!(f, " POLICY {policy}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamTicket {
+    pub id: u64,
+    pub token: String,
+}
+
+impl StreamTicket {
+    pub fn ping(&self) -> String {
+        format!("PING {} {}", self.id, self.token)
+    }
+
+    pub fn pong(&self) -> String {
+        format!("PONG {}", self.id)
+    }
+
+    pub fn proof(&self, nonce: &str) -> String {
+        format!("PROOF {} {nonce}", self.id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamAccepted {
+    pub ticket: StreamTicket,
+    pub endpoint: SocketAddr,
+}
+
+impl fmt::Display for StreamAccepted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "OK {} udp://{} {}",
+            self.ticket.id, self.endpoint, self.ticket.token
+        )
+    }
+}
+
+pub fn parse_accepted(input: &str) -> Result<StreamAccepted, ProtocolError> {
+    let parts: Vec<&str> = input.split_whitespace().collect();
+    let ["OK", id, endpoint, token] = parts.as_slice() else {
+        return Err(ProtocolError::InvalidFormat);
+    };
+    let endpoint = endpoint
+        .strip_prefix("udp://")
+        .ok_or(ProtocolError::InvalidScheme)?
+        .parse()
+        .map_err(|_| ProtocolError::InvalidAddress)?;
+    Ok(StreamAccepted {
+        ticket: StreamTicket {
+            id: id.parse().map_err(|_| ProtocolError::InvalidFormat)?,
+            token: token.to_string(),
+        },
+        endpoint,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthRequest {
+    pub user: String,
+    pub token: String,
+}
+
+impl fmt::Display for AuthRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AUTH {} {}", self.user, self.token)
+    }
+}
+
+pub fn parse_auth(input: &str) -> Result<AuthRequest, ProtocolError> {
+    let parts: Vec<&str> = input.split_whitespace().collect();
+    match parts.as_slice() {
+        [command, user, token] if command.eq_ignore_ascii_case("AUTH") => Ok(AuthRequest {
+            user: user.to_string(),
+            token: token.to_string(),
+        }),
+        _ => Err(ProtocolError::InvalidFormat),
+    }
+}
+
+pub fn parse_ping(input: &str) -> Option<StreamTicket> {
+    let mut parts = input.split_whitespace();
+    if parts.next()? != "PING" {
+        return None;
+    }
+    let id = parts.next()?.parse().ok()?;
+    let token = parts.next()?.to_string();
+    match parts.next() {
+        Some(_) => None,
+        None => Some(StreamTicket { id, token }),
+    }
+}
+
+pub fn parse_pong(input: &str) -> Option<u64> {
+    input.trim().strip_prefix("PONG ")?.parse().ok()
+}
+
+pub fn parse_proof(input: &str) -> Option<(u64, String)> {
+    let parts: Vec<&str> = input.split_whitespace().collect();
+    match parts.as_slice() {
+        ["PROOF", id, nonce] => Some((id.parse().ok()?, nonce.to_string())),
+        _ => None,
+    }
+}
+
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let trimmed = input.trim();
+    let split = trimmed.find(|c: char| !c.is_ascii_digit())?;
+    let (value, unit) = trimmed.split_at(split);
+    let value = value.parse::<u64>().ok()?;
+    match unit.to_ascii_lowercase().as_str() {
+        "ms" => Some(Duration::from_millis(value)),
+        "s" => Some(Duration::from_secs(value)),
+        "m" => Some(Duration::from_secs(value.checked_mul(60)?)),
+        "h" => Some(Duration::from_secs(value.checked_mul(3600)?)),
+        _ => None,
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis() as u64;
+    match millis {
+        0 => "0ms".to_string(),
+        _ if millis.is_multiple_of(3_600_000) => format!("{}h", millis / 3_600_000),
+        _ if millis.is_multiple_of(60_000) => format!("{}m", millis / 60_000),
+        _ if millis.is_multiple_of(1_000) => format!("{}s", millis / 1_000),
+        _ => format!("{millis}ms"),
+    }
 }
 
 #[cfg(test)]
@@ -72,4 +301,77 @@ mod tests {
         let err = parse_command("STREAM tcp://127.0.0.1:1 AAPL").unwrap_err();
         assert_eq!(err, ProtocolError::InvalidScheme);
     }
+
+    #[test]
+    fn parses_stream_options() {
+        let result = parse_command("STREAM udp://127.0.0.1:9999 AAPL depth").unwrap();
+        assert!(result.options.depth);
+        assert_eq!(result.options.to_string(), " DEPTH");
+        let err = parse_command("STREAM udp://127.0.0.1:9999 AAPL BOGUS").unwrap_err();
+        assert_eq!(err, ProtocolError::InvalidOption);
+        let result = parse_command("STREAM udp://127.0.0.1:9999 AAPL BARS 5m DEPTH").unwrap();
+        assert_eq!(result.options.bars, Some(Duration::from_secs(300)));
+        assert_eq!(result.options.to_string(), " DEPTH BARS 5m");
+        let result = parse_command("STREAM udp://127.0.0.1:9999 AAPL CONFLATE 250ms").unwrap();
+        assert_eq!(result.options.conflate, Some(Duration::from_millis(250)));
+        let result = parse_command("STREAM udp://127.0.0.1:9999 AAPL POLICY Disconnect").unwrap();
+        assert_eq!(result.options.overflow, Some(OverflowPolicy::Disconnect));
+        assert_eq!(result.options.to_string(), " POLICY disconnect");
+        let err = parse_command("STREAM udp://127.0.0.1:9999 AAPL BARS").unwrap_err();
+        assert_eq!(err, ProtocolError::InvalidOption);
+        let err =
+            parse_command("STREAM udp://127.0.0.1:9999 AAPL BARS 1m CONFLATE 1s").unwrap_err();
+        assert_eq!(err, ProtocolError::ConflictingOptions);
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("5m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_duration("10"), None);
+        assert_eq!(parse_duration("1d"), None);
+        assert_eq!(parse_duration("999999999999999999h"), None);
+        assert_eq!(parse_duration("999999999999999999m"), None);
+        let err = parse_command("STREAM udp://127.0.0.1:9999 AAPL BARS 999999999999999999h");
+        assert_eq!(err.unwrap_err(), ProtocolError::InvalidOption);
+    }
+
+    #[test]
+    fn roundtrips_accepted_reply_and_pings() {
+        let accepted = StreamAccepted {
+            ticket: StreamTicket {
+                id: 7,
+                token: "9f86d081".to_string(),
+            },
+            endpoint: "127.0.0.1:40000".parse().unwrap(),
+        };
+        let reply = accepted.to_string();
+        assert_eq!(reply, "OK 7 udp://127.0.0.1:40000 9f86d081");
+        assert_eq!(parse_accepted(&reply).unwrap(), accepted);
+        assert_eq!(parse_accepted("OK"), Err(ProtocolError::InvalidFormat));
+
+        assert_eq!(
+            parse_ping(&accepted.ticket.ping()),
+            Some(accepted.ticket.clone())
+        );
+        assert_eq!(parse_ping("ping 7 9f86d081"), None);
+        assert_eq!(parse_ping("PING 7"), None);
+        assert_eq!(parse_pong(&accepted.ticket.pong()), Some(7));
+        assert_eq!(
+            parse_proof(&accepted.ticket.proof("c0ffee")),
+            Some((7, "c0ffee".to_string()))
+        );
+        assert_eq!(parse_proof("PROOF 7"), None);
+    }
+
+    #[test]
+    fn parses_auth_command() {
+        let auth = parse_auth("auth alice s3cret\n").unwrap();
+        assert_eq!(auth.user, "alice");
+        assert_eq!(auth.token, "s3cret");
+        assert_eq!(parse_auth(&auth.to_string()).unwrap(), auth);
+        assert_eq!(parse_auth("AUTH alice"), Err(ProtocolError::InvalidFormat));
+        assert!(parse_auth("STREAM udp://127.0.0.1:9999 AAPL").is_err());
+    }
 }
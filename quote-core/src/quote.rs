@@ -1,3 +1,6 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -6,13 +9,11 @@ pub struct StockQuote {
     pub price: f64,
     pub volume: u32,
     pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
 }
 
 impl StockQuote {
-    pub fn to_string(&self) -> String {
-        serde_json::to_string(self).unwrap()
-    }
-
     pub fn from_string(s: &str) -> Option<Self> {
         serde_json::from_str(s).ok()
     }
@@ -22,6 +23,20 @@ impl StockQuote {
     }
 }
 
+impl fmt::Display for StockQuote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let encoded = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        write!(f, "{encoded}")
+    }
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -33,6 +48,7 @@ mod tests {
             price: 150.25,
             volume: 1200,
             timestamp: 123456,
+            seq: None,
         };
         let encoded = quote.to_string();
         let decoded = StockQuote::from_string(&encoded).unwrap();
@@ -0,0 +1,254 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::quote::StockQuote;
+
+type QuoteIter = Box<dyn Iterator<Item = Result<StockQuote, ReplayError>> + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    Original,
+    Factor(f64),
+    Max,
+}
+
+impl FromStr for ReplaySpeed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim().to_lowercase();
+        match value.as_str() {
+            "original" | "1x" => Ok(ReplaySpeed::Original),
+            "max" => Ok(ReplaySpeed::Max),
+            _ => {
+                let factor = value
+                    .strip_suffix('x')
+                    .unwrap_or(&value)
+                    .parse::<f64>()
+                    .map_err(|_| format!("invalid replay speed: {s}"))?;
+                if factor.is_finite() && factor > 0.0 {
+                    Ok(ReplaySpeed::Factor(factor))
+                } else {
+                    Err(format!("invalid replay speed: {s}"))
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, String),
+    UnknownFormat(PathBuf),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            ReplayError::Parse(path, msg) => write!(f, "{}: {msg}", path.display()),
+            ReplayError::UnknownFormat(path) => {
+                write!(f, "{}: unknown replay format", path.display())
+            }
+        }
+    }
+}
+
+pub struct ReplaySource {
+    files: Vec<QuoteIter>,
+    heads: BinaryHeap<Reverse<(u64, usize)>>,
+    pending: Vec<Option<StockQuote>>,
+    speed: ReplaySpeed,
+    anchor: Option<(Instant, u64)>,
+}
+
+impl ReplaySource {
+    pub fn from_files(paths: &[PathBuf], speed: ReplaySpeed) -> Result<Self, ReplayError> {
+        let files = paths
+            .iter()
+            .map(|path| open_quotes(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        ReplaySource::from_iters(files, speed)
+    }
+
+    pub fn from_quotes(quotes: Vec<StockQuote>, speed: ReplaySpeed) -> Self {
+        let iter: QuoteIter = Box::new(quotes.into_iter().map(Ok));
+        ReplaySource::from_iters(vec![iter], speed).expect("in-memory quotes are infallible")
+    }
+
+    fn from_iters(mut files: Vec<QuoteIter>, speed: ReplaySpeed) -> Result<Self, ReplayError> {
+        let mut heads = BinaryHeap::new();
+        let mut pending = Vec::with_capacity(files.len());
+        for (index, file) in files.iter_mut().enumerate() {
+            let head = file.next().transpose()?;
+            if let Some(quote) = &head {
+                heads.push(Reverse((quote.timestamp, index)));
+            }
+            pending.push(head);
+        }
+        Ok(ReplaySource {
+            files,
+            heads,
+            pending,
+            speed,
+            anchor: None,
+        })
+    }
+
+    pub fn delay_until_next(&self, now: Instant) -> Option<Duration> {
+        let Reverse((timestamp, _)) = self.heads.peek()?;
+        let Some((started, first)) = self.anchor else {
+            return Some(Duration::ZERO);
+        };
+        let offset = Duration::from_millis(timestamp.saturating_sub(first));
+        let scaled = match self.speed {
+            ReplaySpeed::Original => offset,
+            ReplaySpeed::Factor(factor) => offset.div_f64(factor),
+            ReplaySpeed::Max => Duration::ZERO,
+        };
+        Some((started + scaled).saturating_duration_since(now))
+    }
+
+    pub fn next_batch(&mut self) -> Option<Result<Vec<StockQuote>, ReplayError>> {
+        let wait = self.delay_until_next(Instant::now())?;
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+        Some(self.take_due())
+    }
+
+    fn take_due(&mut self) -> Result<Vec<StockQuote>, ReplayError> {
+        let mut batch = Vec::new();
+        let Some(Reverse((timestamp, _))) = self.heads.peek().copied() else {
+            return Ok(batch);
+        };
+        self.anchor.get_or_insert((Instant::now(), timestamp));
+        while let Some(Reverse((head_ts, index))) = self.heads.peek().copied() {
+            if head_ts != timestamp {
+                break;
+            }
+            self.heads.pop();
+            if let Some(quote) = self.pending[index].take() {
+                batch.push(quote);
+            }
+            let next = self.files[index].next().transpose()?;
+            if let Some(quote) = &next {
+                self.heads.push(Reverse((quote.timestamp, index)));
+            }
+            self.pending[index] = next;
+        }
+        Ok(batch)
+    }
+}
+
+impl Iterator for ReplaySource {
+    type Item = Result<Vec<StockQuote>, ReplayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_batch()
+    }
+}
+
+fn open_quotes(path: &Path) -> Result<QuoteIter, ReplayError> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    let file = File::open(path).map_err(|e| ReplayError::Io(path.to_path_buf(), e))?;
+    let owned = path.to_path_buf();
+    match extension.as_str() {
+        "csv" => {
+            let reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(file);
+            Ok(Box::new(reader.into_deserialize::<StockQuote>().map(
+                move |row| row.map_err(|e| ReplayError::Parse(owned.clone(), e.to_string())),
+            )))
+        }
+        "ndjson" | "jsonl" | "json" => {
+            let lines = BufReader::new(file).lines().enumerate();
+            Ok(Box::new(lines.filter_map(move |(index, line)| {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => return Some(Err(ReplayError::Io(owned.clone(), e))),
+                };
+                if line.trim().is_empty() {
+                    return None;
+                }
+                Some(serde_json::from_str(&line).map_err(|e| {
+                    ReplayError::Parse(owned.clone(), format!("line {}: {e}", index + 1))
+                }))
+            })))
+        }
+        _ => Err(ReplayError::UnknownFormat(path.to_path_buf())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn quote(ticker: &str, timestamp: u64) -> StockQuote {
+        StockQuote {
+            ticker: ticker.to_string(),
+            price: 100.0,
+            volume: 10,
+            timestamp,
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn parses_speed() {
+        assert_eq!("original".parse(), Ok(ReplaySpeed::Original));
+        assert_eq!("10x".parse(), Ok(ReplaySpeed::Factor(10.0)));
+        assert_eq!("MAX".parse(), Ok(ReplaySpeed::Max));
+        assert!("-2x".parse::<ReplaySpeed>().is_err());
+    }
+
+    #[test]
+    fn merges_files_by_timestamp() {
+        let dir = tempfile::tempdir().unwrap();
+        let csv_path = dir.path().join("a.csv");
+        let json_path = dir.path().join("b.ndjson");
+        let mut csv_file = File::create(&csv_path).unwrap();
+        writeln!(csv_file, "ticker,price,volume,timestamp").unwrap();
+        writeln!(csv_file, "AAPL,150.5,100,1000").unwrap();
+        writeln!(csv_file, "AAPL,151.0,200,3000").unwrap();
+        let mut json_file = File::create(&json_path).unwrap();
+        writeln!(json_file, "{}", quote("TSLA", 1000)).unwrap();
+        writeln!(json_file, "{}", quote("TSLA", 2000)).unwrap();
+        let source = ReplaySource::from_files(&[csv_path, json_path], ReplaySpeed::Max).unwrap();
+        let batches = source.collect::<Result<Vec<_>, _>>().unwrap();
+        let stamps = batches
+            .iter()
+            .map(|b| b.iter().map(|q| q.timestamp).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(stamps, vec![vec![1000, 1000], vec![2000], vec![3000]]);
+        assert_eq!(batches[0][0].price, 150.5);
+    }
+
+    #[test]
+    fn paces_by_speed_factor() {
+        let quotes = vec![quote("AAPL", 0), quote("AAPL", 10_000)];
+        let mut source = ReplaySource::from_quotes(quotes, ReplaySpeed::Factor(10.0));
+        assert_eq!(
+            source.delay_until_next(Instant::now()),
+            Some(Duration::ZERO)
+        );
+        source.next_batch().unwrap().unwrap();
+        let delay = source.delay_until_next(Instant::now()).unwrap();
+        assert!(delay <= Duration::from_secs(1) && delay > Duration::from_millis(900));
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer};
+
+use crate::protocol::parse_duration;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ScenarioAction {
+    Shock {
+        percent: f64,
+    },
+    Halt {
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    Resume,
+    Volatility {
+        multiplier: f64,
+        #[serde(deserialize_with = "deserialize_duration")]
+        duration: Duration,
+    },
+    FatFinger {
+        percent: f64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ScenarioEvent {
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub at: Duration,
+    #[serde(default)]
+    pub tickers: Vec<String>,
+    #[serde(default)]
+    pub sector: Option<String>,
+    #[serde(flatten)]
+    pub action: ScenarioAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+pub struct Scenario {
+    #[serde(default)]
+    pub sectors: HashMap<String, Vec<String>>,
+    pub events: Vec<ScenarioEvent>,
+}
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(std::io::Error),
+    Parse(String),
+    UnknownSector(String),
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioError::Io(err) => write!(f, "{err}"),
+            ScenarioError::Parse(msg) => write!(f, "invalid scenario: {msg}"),
+            ScenarioError::UnknownSector(name) => write!(f, "unknown sector: {name}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledAction {
+    pub tickers: Vec<String>,
+    pub action: ScenarioAction,
+}
+
+impl Scenario {
+    pub fn parse(input: &str) -> Result<Self, ScenarioError> {
+        let mut scenario: Scenario =
+            serde_json::from_str(input).map_err(|e| ScenarioError::Parse(e.to_string()))?;
+        for event in &scenario.events {
+            if let Some(sector) = &event.sector
+                && !scenario.sectors.contains_key(sector)
+            {
+                return Err(ScenarioError::UnknownSector(sector.clone()));
+            }
+        }
+        scenario.events.sort_by_key(|event| event.at);
+        Ok(scenario)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ScenarioError> {
+        let data = std::fs::read_to_string(path).map_err(ScenarioError::Io)?;
+        Scenario::parse(&data)
+    }
+
+    fn resolve(&self, event: &ScenarioEvent) -> Vec<String> {
+        let mut tickers = event
+            .tickers
+            .iter()
+            .map(|t| t.to_uppercase())
+            .collect::<Vec<_>>();
+        if let Some(members) = event.sector.as_ref().and_then(|s| self.sectors.get(s)) {
+            tickers.extend(members.iter().map(|t| t.to_uppercase()));
+        }
+        tickers
+    }
+}
+
+pub struct ScenarioEngine {
+    scenario: Scenario,
+    start: u64,
+    cursor: usize,
+}
+
+impl ScenarioEngine {
+    pub fn new(scenario: Scenario, start: u64) -> Self {
+        ScenarioEngine {
+            scenario,
+            start,
+            cursor: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.cursor >= self.scenario.events.len()
+    }
+
+    pub fn due(&mut self, now: u64) -> Vec<ScheduledAction> {
+        let mut actions = Vec::new();
+        while let Some(event) = self.scenario.events.get(self.cursor) {
+            if self.start + event.at.as_millis() as u64 > now {
+                break;
+            }
+            actions.push(ScheduledAction {
+                tickers: self.scenario.resolve(event),
+                action: event.action.clone(),
+            });
+            self.cursor += 1;
+        }
+        actions
+    }
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let raw = String::deserialize(deserializer)?;
+    parse_duration(&raw).ok_or_else(|| serde::de::Error::custom(format!("invalid duration: {raw}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"{
+        "sectors": { "tech": ["AAPL", "msft"] },
+        "events": [
+            { "at": "45s", "action": "halt", "tickers": ["TSLA"], "reason": "news" },
+            { "at": "30s", "action": "shock", "sector": "tech", "percent": -10 },
+            { "at": "60s", "action": "volatility", "multiplier": 4, "duration": "20s" }
+        ]
+    }"#;
+
+    #[test]
+    fn parses_and_orders_events() {
+        let scenario = Scenario::parse(SAMPLE).unwrap();
+        assert_eq!(scenario.events[0].at, Duration::from_secs(30));
+        assert_eq!(
+            scenario.events[2].action,
+            ScenarioAction::Volatility {
+                multiplier: 4.0,
+                duration: Duration::from_secs(20),
+            }
+        );
+        let err = Scenario::parse(r#"{"events":[{"at":"1s","action":"resume","sector":"x"}]}"#);
+        assert!(matches!(err, Err(ScenarioError::UnknownSector(_))));
+    }
+
+    #[test]
+    fn engine_releases_due_actions() {
+        let mut engine = ScenarioEngine::new(Scenario::parse(SAMPLE).unwrap(), 1_000);
+        assert!(engine.due(30_000).is_empty());
+        let shock = engine.due(31_000);
+        assert_eq!(shock.len(), 1);
+        assert_eq!(
+            shock[0].tickers,
+            vec!["AAPL".to_string(), "MSFT".to_string()]
+        );
+        assert_eq!(engine.due(100_000).len(), 2);
+        assert!(engine.is_finished());
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use crate::event::MarketEvent;
+
+#[derive(Debug, Default)]
+pub struct Sequencer {
+    last: HashMap<String, u64>,
+}
+
+impl Sequencer {
+    pub fn stamp(&mut self, event: &mut MarketEvent) {
+        let MarketEvent::Quote(quote) = event else {
+            return;
+        };
+        let last = self.last.entry(quote.ticker.clone()).or_default();
+        match quote.seq {
+            Some(seq) => *last = seq,
+            None => {
+                *last += 1;
+                quote.seq = Some(*last);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quote::StockQuote;
+
+    fn quote(ticker: &str, seq: Option<u64>) -> MarketEvent {
+        MarketEvent::Quote(StockQuote {
+            ticker: ticker.to_string(),
+            price: 1.0,
+            volume: 1,
+            timestamp: 0,
+            seq,
+        })
+    }
+
+    fn seq(event: &MarketEvent) -> Option<u64> {
+        match event {
+            MarketEvent::Quote(quote) => quote.seq,
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn numbers_quotes_per_ticker_and_keeps_upstream_sequence() {
+        let mut sequencer = Sequencer::default();
+        let mut events = vec![
+            quote("AAPL", None),
+            quote("TSLA", None),
+            quote("AAPL", None),
+            quote("AAPL", Some(10)),
+            quote("AAPL", None),
+        ];
+        for event in &mut events {
+            sequencer.stamp(event);
+        }
+        let seqs: Vec<_> = events.iter().map(seq).collect();
+        assert_eq!(seqs, vec![Some(1), Some(1), Some(2), Some(10), Some(11)]);
+    }
+}
@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+
+const MINUTE_MS: i64 = 60_000;
+const DAY_MINUTES: i64 = 24 * 60;
+const EPOCH_WEEKDAY: i64 = 3;
+const SATURDAY: i64 = 5;
+const SUNDAY: i64 = 6;
+const DST_SHIFT_MINUTES: i64 = 60;
+const DST_SWITCH_MINUTE: i64 = 2 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MarketSession {
+    PreMarket,
+    Open,
+    Close,
+    AfterHours,
+    Closed,
+}
+
+impl MarketSession {
+    pub fn allows_trading(self) -> bool {
+        self != MarketSession::Closed
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionSchedule {
+    pub utc_offset_minutes: i32,
+    pub us_dst: bool,
+    pub pre_market: u32,
+    pub open: u32,
+    pub close: u32,
+    pub after_hours: u32,
+    pub end: u32,
+}
+
+impl SessionSchedule {
+    pub fn us_equities() -> Self {
+        SessionSchedule {
+            utc_offset_minutes: -5 * 60,
+            us_dst: true,
+            pre_market: 4 * 60,
+            open: 9 * 60 + 30,
+            close: 15 * 60 + 50,
+            after_hours: 16 * 60,
+            end: 20 * 60,
+        }
+    }
+
+    pub fn session_at(&self, timestamp: u64) -> MarketSession {
+        let mut local = timestamp as i64 / MINUTE_MS + self.utc_offset_minutes as i64;
+        if self.us_dst && in_us_dst(local) {
+            local += DST_SHIFT_MINUTES;
+        }
+        let day = local.div_euclid(DAY_MINUTES);
+        let weekday = (day + EPOCH_WEEKDAY).rem_euclid(7);
+        if weekday >= SATURDAY {
+            return MarketSession::Closed;
+        }
+        let minute = local.rem_euclid(DAY_MINUTES) as u32;
+        if minute < self.pre_market || minute >= self.end {
+            MarketSession::Closed
+        } else if minute < self.open {
+            MarketSession::PreMarket
+        } else if minute < self.close {
+            MarketSession::Open
+        } else if minute < self.after_hours {
+            MarketSession::Close
+        } else {
+            MarketSession::AfterHours
+        }
+    }
+}
+
+impl Default for SessionSchedule {
+    fn default() -> Self {
+        SessionSchedule::us_equities()
+    }
+}
+
+fn in_us_dst(standard_minutes: i64) -> bool {
+    let year = year_of(standard_minutes.div_euclid(DAY_MINUTES));
+    let start = nth_sunday(year, 3, 2) * DAY_MINUTES + DST_SWITCH_MINUTE;
+    let end = nth_sunday(year, 11, 1) * DAY_MINUTES + DST_SWITCH_MINUTE - DST_SHIFT_MINUTES;
+    (start..end).contains(&standard_minutes)
+}
+
+fn nth_sunday(year: i64, month: i64, nth: i64) -> i64 {
+    let first = days_from_civil(year, month, 1);
+    let weekday = (first + EPOCH_WEEKDAY).rem_euclid(7);
+    first + (SUNDAY - weekday).rem_euclid(7) + (nth - 1) * 7
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn year_of(days: i64) -> i64 {
+    let shifted = days + 719_468;
+    let era = shifted.div_euclid(146_097);
+    let day_of_era = shifted - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let year = year_of_era + era * 400;
+    if month_index >= 10 { year + 1 } else { year }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MONDAY_UTC_MIDNIGHT: u64 = 1_704_672_000_000;
+
+    fn at(day: u64, hour: u64, minute: u64) -> u64 {
+        MONDAY_UTC_MIDNIGHT + ((day * 24 + hour) * 60 + minute) * 60_000
+    }
+
+    #[test]
+    fn maps_time_of_day_to_session() {
+        let schedule = SessionSchedule::us_equities();
+        assert_eq!(schedule.session_at(at(0, 8, 0)), MarketSession::Closed);
+        assert_eq!(schedule.session_at(at(0, 10, 0)), MarketSession::PreMarket);
+        assert_eq!(schedule.session_at(at(0, 14, 30)), MarketSession::Open);
+        assert_eq!(schedule.session_at(at(0, 20, 55)), MarketSession::Close);
+        assert_eq!(schedule.session_at(at(0, 22, 0)), MarketSession::AfterHours);
+        assert_eq!(schedule.session_at(at(5, 15, 0)), MarketSession::Closed);
+    }
+
+    #[test]
+    fn follows_us_daylight_saving_time() {
+        let schedule = SessionSchedule::us_equities();
+        let march_8 = 1_709_856_000_000;
+        let march_11 = 1_710_115_200_000;
+        let november_4 = 1_730_678_400_000;
+        let minute = 60_000;
+        assert_eq!(
+            schedule.session_at(march_8 + (13 * 60 + 30) * minute),
+            MarketSession::PreMarket
+        );
+        assert_eq!(
+            schedule.session_at(march_11 + (13 * 60 + 30) * minute),
+            MarketSession::Open
+        );
+        assert_eq!(
+            schedule.session_at(november_4 + (13 * 60 + 30) * minute),
+            MarketSession::PreMarket
+        );
+        let fixed = SessionSchedule {
+            us_dst: false,
+            ..schedule
+        };
+        assert_eq!(
+            fixed.session_at(march_11 + (13 * 60 + 30) * minute),
+            MarketSession::PreMarket
+        );
+    }
+}
@@ -0,0 +1,438 @@
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, UdpSocket};
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::event::{ControlEvent, ControlKind, MarketEvent};
+use crate::generator::QuoteGenerator;
+use crate::protocol::{AuthRequest, StreamAccepted, parse_accepted};
+use crate::quote::now_millis;
+use crate::replay::{ReplayError, ReplaySource};
+#[cfg(feature = "tls")]
+use crate::tls::Control;
+
+const GENERATOR_MAX_IDLE: Duration = Duration::from_millis(200);
+const REPLAY_MAX_IDLE: Duration = Duration::from_millis(200);
+const UPSTREAM_READ_TIMEOUT: Duration = Duration::from_millis(200);
+const UPSTREAM_TCP_TIMEOUT: Duration = Duration::from_secs(3);
+const UPSTREAM_PING_INTERVAL: Duration = Duration::from_secs(2);
+const UPSTREAM_RECV_BUF: usize = 1024;
+const UPSTREAM_BYE: &str = "BYE";
+const UPSTREAM_RETRY_MIN: Duration = Duration::from_millis(500);
+const UPSTREAM_RETRY_MAX: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum SourceError {
+    Replay(ReplayError),
+    Io(std::io::Error),
+    Rejected(String),
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceError::Replay(err) => write!(f, "replay: {err}"),
+            SourceError::Io(err) => write!(f, "io: {err}"),
+            SourceError::Rejected(reply) => write!(f, "rejected: {reply}"),
+        }
+    }
+}
+
+impl From<ReplayError> for SourceError {
+    fn from(err: ReplayError) -> Self {
+        SourceError::Replay(err)
+    }
+}
+
+impl From<std::io::Error> for SourceError {
+    fn from(err: std::io::Error) -> Self {
+        SourceError::Io(err)
+    }
+}
+
+pub trait QuoteSource: Send {
+    fn next_batch(&mut self) -> Option<Result<Vec<MarketEvent>, SourceError>>;
+}
+
+impl QuoteSource for QuoteGenerator {
+    fn next_batch(&mut self) -> Option<Result<Vec<MarketEvent>, SourceError>> {
+        let wait = self
+            .next_due()
+            .map(|due| Duration::from_millis(due.saturating_sub(now_millis())))
+            .unwrap_or(GENERATOR_MAX_IDLE);
+        thread::sleep(wait.min(GENERATOR_MAX_IDLE));
+        Some(Ok(self.poll(now_millis())))
+    }
+}
+
+impl QuoteSource for ReplaySource {
+    fn next_batch(&mut self) -> Option<Result<Vec<MarketEvent>, SourceError>> {
+        if self.delay_until_next(Instant::now())? > REPLAY_MAX_IDLE {
+            thread::sleep(REPLAY_MAX_IDLE);
+            return Some(Ok(Vec::new()));
+        }
+        ReplaySource::next_batch(self).map(|batch| {
+            batch
+                .map(|quotes| quotes.into_iter().map(MarketEvent::from).collect())
+                .map_err(SourceError::from)
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UpstreamConfig {
+    pub server: String,
+    pub tickers: Vec<String>,
+    pub auth: Option<AuthRequest>,
+    #[cfg(feature = "tls")]
+    pub tls: Option<Arc<rustls::ClientConfig>>,
+}
+
+impl UpstreamConfig {
+    pub fn new(server: &str, tickers: &[String]) -> Self {
+        UpstreamConfig {
+            server: server.to_string(),
+            tickers: tickers.to_vec(),
+            auth: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+type UpstreamControl = Control<rustls::ClientConnection>;
+#[cfg(not(feature = "tls"))]
+type UpstreamControl = TcpStream;
+
+struct UpstreamLink {
+    socket: UdpSocket,
+    control: BufReader<UpstreamControl>,
+    partial: Vec<u8>,
+    accepted: StreamAccepted,
+    last_ping: Instant,
+}
+
+impl UpstreamLink {
+    fn open(config: &UpstreamConfig) -> Result<Self, SourceError> {
+        let stream = TcpStream::connect(&config.server)?;
+        stream.set_read_timeout(Some(UPSTREAM_TCP_TIMEOUT))?;
+        let local_ip = stream.local_addr()?.ip();
+        let socket = UdpSocket::bind((local_ip, 0))?;
+        socket.set_read_timeout(Some(UPSTREAM_READ_TIMEOUT))?;
+        let command = format!(
+            "STREAM udp://{} {}\n",
+            socket.local_addr()?,
+            config.tickers.join(",")
+        );
+        #[cfg(feature = "tls")]
+        let stream = Control::connect(stream, &config.server, config.tls.as_ref())?;
+        let mut control = BufReader::new(stream);
+        if let Some(auth) = &config.auth {
+            let reply = exchange(&mut control, &format!("{auth}\n"))?;
+            if !reply.starts_with("OK") {
+                return Err(SourceError::Rejected(reply));
+            }
+        }
+        let reply = exchange(&mut control, &command)?;
+        let accepted = parse_accepted(&reply).map_err(|_| SourceError::Rejected(reply))?;
+        socket_of(control.get_ref()).set_nonblocking(true)?;
+        Ok(UpstreamLink {
+            socket,
+            control,
+            partial: Vec::new(),
+            accepted,
+            last_ping: Instant::now() - UPSTREAM_PING_INTERVAL,
+        })
+    }
+
+    fn ping_if_due(&mut self) {
+        if self.last_ping.elapsed() >= UPSTREAM_PING_INTERVAL {
+            let ping = self.accepted.ticket.ping();
+            let _ = self.socket.send_to(ping.as_bytes(), self.accepted.endpoint);
+            self.last_ping = Instant::now();
+        }
+    }
+
+    fn control_closed(&mut self) -> bool {
+        loop {
+            match self.control.read_until(b'\n', &mut self.partial) {
+                Ok(_) if self.partial.ends_with(b"\n") => {
+                    let line = String::from_utf8_lossy(&self.partial).trim().to_string();
+                    self.partial.clear();
+                    if line == UPSTREAM_BYE {
+                        return true;
+                    }
+                    warn!("ignoring upstream control line: {line}");
+                }
+                Ok(_) => return true,
+                Err(ref err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    return self.partial.len() > UPSTREAM_RECV_BUF;
+                }
+                Err(_) => return true,
+            }
+        }
+    }
+
+    fn receive(&mut self) -> Result<Vec<MarketEvent>, String> {
+        self.ping_if_due();
+        if self.control_closed() {
+            return Err("control connection closed".to_string());
+        }
+        let mut buf = [0u8; UPSTREAM_RECV_BUF];
+        let mut batch = Vec::new();
+        match self.socket.recv_from(&mut buf) {
+            Ok((n, _)) => {
+                match std::str::from_utf8(&buf[..n])
+                    .ok()
+                    .and_then(MarketEvent::from_string)
+                {
+                    Some(MarketEvent::Control(ControlEvent {
+                        control: ControlKind::Challenge,
+                        nonce: Some(nonce),
+                        ..
+                    })) => {
+                        let proof = self.accepted.ticket.proof(&nonce);
+                        let _ = self
+                            .socket
+                            .send_to(proof.as_bytes(), self.accepted.endpoint);
+                    }
+                    Some(MarketEvent::Control(ControlEvent {
+                        control: ControlKind::Shutdown,
+                        ..
+                    })) => return Err("upstream shut down".to_string()),
+                    Some(MarketEvent::Control(_)) => {}
+                    Some(event) => batch.push(event),
+                    None => {}
+                }
+            }
+            Err(ref err)
+                if err.kind() == std::io::ErrorKind::WouldBlock
+                    || err.kind() == std::io::ErrorKind::TimedOut => {}
+            Err(err) => return Err(err.to_string()),
+        }
+        Ok(batch)
+    }
+}
+
+pub struct UpstreamSource {
+    config: UpstreamConfig,
+    link: Option<UpstreamLink>,
+    retry_delay: Duration,
+    retry_at: Instant,
+}
+
+impl UpstreamSource {
+    pub fn connect(config: UpstreamConfig) -> Result<Self, SourceError> {
+        let link = UpstreamLink::open(&config)?;
+        Ok(UpstreamSource {
+            config,
+            link: Some(link),
+            retry_delay: UPSTREAM_RETRY_MIN,
+            retry_at: Instant::now(),
+        })
+    }
+
+    fn reconnect(&mut self) {
+        let now = Instant::now();
+        if now < self.retry_at {
+            thread::sleep((self.retry_at - now).min(UPSTREAM_READ_TIMEOUT));
+            return;
+        }
+        match UpstreamLink::open(&self.config) {
+            Ok(link) => {
+                info!("upstream {} reconnected", self.config.server);
+                self.link = Some(link);
+                self.retry_delay = UPSTREAM_RETRY_MIN;
+            }
+            Err(err) => {
+                self.retry_delay = (self.retry_delay * 2).min(UPSTREAM_RETRY_MAX);
+                warn!(
+                    "upstream {} reconnect failed: {err}, retrying in {:?}",
+                    self.config.server, self.retry_delay
+                );
+                self.retry_at = now + self.retry_delay;
+            }
+        }
+    }
+}
+
+impl QuoteSource for UpstreamSource {
+    fn next_batch(&mut self) -> Option<Result<Vec<MarketEvent>, SourceError>> {
+        let Some(link) = self.link.as_mut() else {
+            self.reconnect();
+            return Some(Ok(Vec::new()));
+        };
+        match link.receive() {
+            Ok(batch) => Some(Ok(batch)),
+            Err(reason) => {
+                warn!(
+                    "upstream {} lost: {reason}, reconnecting in {:?}",
+                    self.config.server, self.retry_delay
+                );
+                self.link = None;
+                self.retry_at = Instant::now() + self.retry_delay;
+                Some(Ok(Vec::new()))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+fn socket_of(control: &UpstreamControl) -> &TcpStream {
+    control.socket()
+}
+
+#[cfg(not(feature = "tls"))]
+fn socket_of(control: &UpstreamControl) -> &TcpStream {
+    control
+}
+
+fn exchange(control: &mut BufReader<UpstreamControl>, line: &str) -> Result<String, SourceError> {
+    let writer = control.get_mut();
+    writer.write_all(line.as_bytes())?;
+    writer.flush()?;
+    let mut reply = String::new();
+    control.read_line(&mut reply)?;
+    Ok(reply.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::quote::StockQuote;
+    use crate::replay::ReplaySpeed;
+
+    #[test]
+    fn drains_replay_through_trait() {
+        let quotes = (0..3)
+            .map(|i| StockQuote {
+                ticker: "AAPL".to_string(),
+                price: 100.0 + i as f64,
+                volume: 10,
+                timestamp: i,
+                seq: None,
+            })
+            .collect::<Vec<_>>();
+        let mut source: Box<dyn QuoteSource> =
+            Box::new(ReplaySource::from_quotes(quotes, ReplaySpeed::Max));
+        let mut count = 0;
+        while let Some(batch) = source.next_batch() {
+            count += batch.unwrap().len();
+        }
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn replay_gap_does_not_block_the_caller() {
+        let quotes = [0, 60_000]
+            .into_iter()
+            .map(|timestamp| StockQuote {
+                ticker: "AAPL".to_string(),
+                price: 100.0,
+                volume: 10,
+                timestamp,
+                seq: None,
+            })
+            .collect::<Vec<_>>();
+        let mut source = ReplaySource::from_quotes(quotes, ReplaySpeed::Original);
+        let started = Instant::now();
+        let first = QuoteSource::next_batch(&mut source).unwrap().unwrap();
+        let idle = QuoteSource::next_batch(&mut source).unwrap().unwrap();
+        assert_eq!(first.len(), 1);
+        assert!(idle.is_empty());
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    fn serve_upstream(listener: &TcpListener, shut_down: bool) {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if let Ok(auth) = crate::protocol::parse_auth(&line) {
+            assert_eq!(auth.token, "s3cret");
+            writer
+                .write_all(format!("OK {}\n", auth.user).as_bytes())
+                .unwrap();
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+        let request = crate::protocol::parse_command(&line).unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let accepted = StreamAccepted {
+            ticket: crate::protocol::StreamTicket {
+                id: 1,
+                token: "feed".to_string(),
+            },
+            endpoint: socket.local_addr().unwrap(),
+        };
+        writer
+            .write_all(format!("{accepted}\n").as_bytes())
+            .unwrap();
+        if shut_down {
+            let event = MarketEvent::Control(ControlEvent::shutdown("restart"));
+            socket.send_to(&event.to_bytes(), request.addr).unwrap();
+            return;
+        }
+        writer.write_all(b"NOTICE maintenance at 22:00\n").unwrap();
+        let quote = StockQuote {
+            ticker: request.tickers[0].clone(),
+            price: 10.0,
+            volume: 1,
+            timestamp: 1,
+            seq: None,
+        };
+        for _ in 0..20 {
+            let _ = socket.send_to(quote.to_string().as_bytes(), request.addr);
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn first_quotes(source: &mut UpstreamSource, attempts: usize) -> Vec<MarketEvent> {
+        let mut received = Vec::new();
+        for _ in 0..attempts {
+            received.extend(source.next_batch().unwrap().unwrap());
+            if !received.is_empty() {
+                break;
+            }
+        }
+        received
+    }
+
+    #[test]
+    fn upstream_subscribes_and_receives_quotes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve_upstream(&listener, false));
+        let mut config = UpstreamConfig::new(&addr.to_string(), &["MSFT".to_string()]);
+        config.auth = Some(AuthRequest {
+            user: "relay".to_string(),
+            token: "s3cret".to_string(),
+        });
+        let mut source = UpstreamSource::connect(config).unwrap();
+        let received = first_quotes(&mut source, 20);
+        assert_eq!(received[0].ticker(), Some("MSFT"));
+    }
+
+    #[test]
+    fn upstream_reconnects_after_shutdown() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            serve_upstream(&listener, true);
+            serve_upstream(&listener, false);
+        });
+        let config = UpstreamConfig::new(&addr.to_string(), &["MSFT".to_string()]);
+        let mut source = UpstreamSource::connect(config).unwrap();
+        let received = first_quotes(&mut source, 50);
+        assert_eq!(received[0].ticker(), Some("MSFT"));
+    }
+}
@@ -0,0 +1,154 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConnection, ConnectionCommon, RootCertStore, ServerConnection, SideData};
+use rustls::{ServerConfig, StreamOwned};
+
+pub fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    if certs.is_empty() {
+        return Err(format!("no certificates in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+pub fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    PrivateKeyDer::from_pem_file(path).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+pub fn client_config(
+    ca: &Path,
+    identity: Option<(&Path, &Path)>,
+) -> Result<Arc<rustls::ClientConfig>, String> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(ca)? {
+        roots.add(cert).map_err(|e| e.to_string())?;
+    }
+    let builder = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_root_certificates(roots);
+    let config = match identity {
+        Some((cert, key)) => builder
+            .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+            .map_err(|e| e.to_string())?,
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(config))
+}
+
+pub fn server_name(server: &str) -> std::io::Result<ServerName<'static>> {
+    let host = server.rsplit_once(':').map_or(server, |(host, _)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    ServerName::try_from(host.to_string())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+}
+
+pub enum Control<C> {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<C, TcpStream>>),
+}
+
+impl<C> Control<C> {
+    pub fn socket(&self) -> &TcpStream {
+        match self {
+            Control::Plain(stream) => stream,
+            Control::Tls(stream) => &stream.sock,
+        }
+    }
+}
+
+impl Control<ServerConnection> {
+    pub fn accept(stream: TcpStream, tls: Option<&Arc<ServerConfig>>) -> std::io::Result<Self> {
+        match tls {
+            Some(config) => {
+                let conn = ServerConnection::new(config.clone()).map_err(std::io::Error::other)?;
+                Ok(Control::Tls(Box::new(StreamOwned::new(conn, stream))))
+            }
+            None => Ok(Control::Plain(stream)),
+        }
+    }
+}
+
+impl Control<ClientConnection> {
+    pub fn connect(
+        stream: TcpStream,
+        server: &str,
+        tls: Option<&Arc<rustls::ClientConfig>>,
+    ) -> std::io::Result<Self> {
+        match tls {
+            Some(config) => {
+                let conn = ClientConnection::new(config.clone(), server_name(server)?)
+                    .map_err(std::io::Error::other)?;
+                Ok(Control::Tls(Box::new(StreamOwned::new(conn, stream))))
+            }
+            None => Ok(Control::Plain(stream)),
+        }
+    }
+}
+
+impl<C, S> Read for Control<C>
+where
+    C: Deref<Target = ConnectionCommon<S>> + DerefMut,
+    S: SideData,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Control::Plain(stream) => stream.read(buf),
+            Control::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl<C, S> Write for Control<C>
+where
+    C: Deref<Target = ConnectionCommon<S>> + DerefMut,
+    S: SideData,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Control::Plain(stream) => stream.write(buf),
+            Control::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Control::Plain(stream) => stream.flush(),
+            Control::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write_pki;
+
+    #[test]
+    fn loads_pem_files_and_parses_server_names() {
+        assert!(server_name("127.0.0.1:7878").is_ok());
+        assert!(server_name("[::1]:7878").is_ok());
+        assert!(server_name("quotes.example.com:7878").is_ok());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        write_pki(dir.path());
+        assert_eq!(load_certs(&path("ca.pem")).unwrap().len(), 1);
+        assert!(load_certs(&path("server.key")).is_err());
+        assert!(load_key(&path("missing.key")).is_err());
+        let identity = Some((path("client.pem"), path("client.key")));
+        let identity = identity
+            .as_ref()
+            .map(|(cert, key)| (cert.as_path(), key.as_path()));
+        assert!(client_config(&path("ca.pem"), identity).is_ok());
+    }
+}
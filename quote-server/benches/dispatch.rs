@@ -0,0 +1,71 @@
+use std::collections::HashSet;
+use std::hint::black_box;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use quote_server::registry::Registry;
+
+const TICKERS: usize = 100;
+const PER_CLIENT: usize = 3;
+
+fn ticker(i: usize) -> String {
+    format!("T{i:03}")
+}
+
+fn client_tickers(client: usize) -> Vec<String> {
+    (0..PER_CLIENT)
+        .map(|k| ticker((client * 7 + k * 31) % TICKERS))
+        .collect()
+}
+
+fn dispatch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dispatch");
+    for clients in [1_000, 5_000] {
+        let linear = Mutex::new(
+            (0..clients)
+                .map(|i| {
+                    (
+                        client_tickers(i).into_iter().collect::<HashSet<_>>(),
+                        AtomicU64::new(0),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        );
+        let registry = Registry::new();
+        for i in 0..clients {
+            registry.insert(&client_tickers(i), AtomicU64::new(0));
+        }
+        let quotes = (0..TICKERS).map(ticker).collect::<Vec<_>>();
+
+        group.bench_with_input(BenchmarkId::new("linear", clients), &quotes, |b, quotes| {
+            b.iter(|| {
+                for quote in quotes {
+                    let guard = linear.lock().unwrap();
+                    for (filter, sent) in guard.iter() {
+                        if filter.contains(black_box(quote)) {
+                            sent.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+            })
+        });
+        group.bench_with_input(
+            BenchmarkId::new("registry", clients),
+            &quotes,
+            |b, quotes| {
+                b.iter(|| {
+                    for quote in quotes {
+                        registry.for_ticker(black_box(quote), |_, sent| {
+                            sent.fetch_add(1, Ordering::Relaxed);
+                        });
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
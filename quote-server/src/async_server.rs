@@ -0,0 +1,560 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, error, info};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::{Notify, mpsc};
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
+
+use quote_core::{
+    ControlEvent, MarketEvent, QuoteSource, Sequencer, StreamAccepted, StreamRequest, StreamTicket,
+};
+
+use crate::config::ServerConfig;
+use crate::fanout::UDP_BIND_ADDR;
+use crate::handshake::{Handshake, Step};
+use crate::hub::{Hub, StreamDirectory, Wake};
+use crate::queue::QueueReceiver;
+use crate::server::COMMAND_TIMEOUT;
+use crate::shutdown::{BYE, SHUTDOWN_REASON, Shutdown};
+use crate::stream::{DISPATCH_TIMEOUT, Drain, StreamState, ping_ticket, proof_nonce};
+
+const SHUTDOWN_POLL: Duration = Duration::from_millis(50);
+
+type Liveness = Arc<Mutex<Presence>>;
+type ControlReader = Box<dyn AsyncRead + Send + Sync + Unpin>;
+type ControlWriter = Box<dyn AsyncWrite + Send + Sync + Unpin>;
+
+struct Presence {
+    last_seen: Instant,
+    proof: Option<String>,
+    verified: bool,
+}
+
+#[derive(Clone)]
+struct AsyncWaker(Arc<Notify>);
+
+impl Wake for AsyncWaker {
+    fn wake(&self) {
+        self.0.notify_one();
+    }
+}
+
+struct UdpWorker {
+    socket: UdpSocket,
+    port: u16,
+    pings: Mutex<HashMap<u64, (String, Liveness)>>,
+}
+
+struct Shared {
+    hub: Arc<Hub<AsyncWaker>>,
+    workers: Vec<Arc<UdpWorker>>,
+    next: AtomicUsize,
+    streams: Mutex<Vec<JoinHandle<()>>>,
+    closing: Shutdown,
+    acceptor: Option<TlsAcceptor>,
+}
+
+struct StreamTask {
+    worker: Arc<UdpWorker>,
+    id: u64,
+    addr: SocketAddr,
+    state: StreamState,
+    rx: QueueReceiver,
+    notify: Arc<Notify>,
+    liveness: Liveness,
+    keepalive: Duration,
+    control: Option<ControlWriter>,
+}
+
+pub fn run_server_async(
+    addr: &str,
+    config: ServerConfig,
+    source: Box<dyn QuoteSource>,
+    shutdown: &Shutdown,
+) -> std::io::Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let listener = TcpListener::bind(addr).await?;
+        let hub = Arc::new(Hub::new(config));
+        serve(listener, hub, source, shutdown.clone()).await
+    })
+}
+
+pub(crate) fn spawn(
+    listener: std::net::TcpListener,
+    config: ServerConfig,
+    source: Box<dyn QuoteSource>,
+    shutdown: Shutdown,
+) -> (
+    Arc<dyn StreamDirectory>,
+    thread::JoinHandle<std::io::Result<()>>,
+) {
+    let hub = Arc::new(Hub::new(config));
+    let directory = hub.clone();
+    let handle = thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        runtime.block_on(async {
+            listener.set_nonblocking(true)?;
+            let listener = TcpListener::from_std(listener)?;
+            serve(listener, hub, source, shutdown).await
+        })
+    });
+    (directory, handle)
+}
+
+async fn serve(
+    listener: TcpListener,
+    hub: Arc<Hub<AsyncWaker>>,
+    source: Box<dyn QuoteSource>,
+    shutdown: Shutdown,
+) -> std::io::Result<()> {
+    let (quote_tx, quote_rx) = mpsc::channel(hub.config.source_queue.max(1));
+    let mut workers = Vec::new();
+    let mut receivers = Vec::new();
+    for _ in 0..hub.config.udp_workers.max(1) {
+        let socket = UdpSocket::bind(UDP_BIND_ADDR).await?;
+        info!("udp worker on {}", socket.local_addr()?);
+        let worker = Arc::new(UdpWorker {
+            port: socket.local_addr()?.port(),
+            socket,
+            pings: Mutex::new(HashMap::new()),
+        });
+        receivers.push(tokio::spawn(receive_pings(worker.clone())));
+        workers.push(worker);
+    }
+    let acceptor = hub.config.tls.clone().map(TlsAcceptor::from);
+    let shared = Arc::new(Shared {
+        hub,
+        workers,
+        next: AtomicUsize::new(0),
+        streams: Mutex::new(Vec::new()),
+        closing: Shutdown::new(),
+        acceptor,
+    });
+    let source = spawn_source(quote_tx, source, shutdown.clone());
+    let dispatcher = tokio::spawn(dispatch(quote_rx, shared.clone()));
+    let mut connections: Vec<JoinHandle<()>> = Vec::new();
+    info!("listening on {} (async)", listener.local_addr()?);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    info!("tcp connect {peer}");
+                    let shared = shared.clone();
+                    connections.retain(|handle| !handle.is_finished());
+                    connections.push(tokio::spawn(async move {
+                        if let Err(err) = handle_connection(stream, shared).await {
+                            error!("client error: {err}");
+                        }
+                    }));
+                }
+                Err(err) => error!("accept error: {err}"),
+            },
+            _ = triggered(&shutdown) => break,
+        }
+    }
+    info!("shutting down");
+    drop(listener);
+    for handle in connections {
+        let _ = handle.await;
+    }
+    let _ = source.await;
+    let _ = dispatcher.await;
+    shared.closing.trigger();
+    let streams = match shared.streams.lock() {
+        Ok(mut streams) => std::mem::take(&mut *streams),
+        Err(_) => Vec::new(),
+    };
+    for handle in streams {
+        let _ = handle.await;
+    }
+    for handle in receivers {
+        handle.abort();
+    }
+    info!("server stopped");
+    Ok(())
+}
+
+async fn triggered(shutdown: &Shutdown) {
+    while !shutdown.is_triggered() {
+        tokio::time::sleep(SHUTDOWN_POLL).await;
+    }
+}
+
+fn spawn_source(
+    tx: mpsc::Sender<MarketEvent>,
+    mut source: Box<dyn QuoteSource>,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
+    tokio::task::spawn_blocking(move || {
+        let mut sequencer = Sequencer::default();
+        while !shutdown.is_triggered()
+            && let Some(batch) = source.next_batch()
+        {
+            match batch {
+                Ok(events) => {
+                    for mut event in events {
+                        sequencer.stamp(&mut event);
+                        let _ = tx.blocking_send(event);
+                    }
+                }
+                Err(err) => {
+                    error!("source error: {err}");
+                    break;
+                }
+            }
+        }
+        info!("source finished");
+    })
+}
+
+async fn dispatch(mut rx: mpsc::Receiver<MarketEvent>, shared: Arc<Shared>) {
+    while let Some(event) = rx.recv().await {
+        shared.hub.dispatch(&event);
+    }
+}
+
+async fn handle_connection(stream: TcpStream, shared: Arc<Shared>) -> std::io::Result<()> {
+    let local = stream.local_addr()?;
+    let peer = stream.peer_addr()?.ip();
+    let (read, mut write) = split(stream, shared.acceptor.as_ref()).await?;
+    let mut reader = BufReader::new(read);
+    let mut handshake = Handshake::new(&shared.hub, peer);
+    let mut buffer = String::new();
+    let (admission, request) = loop {
+        buffer.clear();
+        read_command(&mut reader, &mut buffer).await?;
+        match handshake.on_line(&buffer) {
+            Step::Reply(line) => send(&mut write, &line).await?,
+            Step::Reject(line) => return send(&mut write, &line).await,
+            Step::Accept(admission, request) => break (admission, request),
+            Step::Close => return Ok(()),
+        }
+    };
+    let worker = shared.next.fetch_add(1, Ordering::Relaxed) % shared.workers.len();
+    let accepted = StreamAccepted {
+        ticket: admission.ticket.clone(),
+        endpoint: SocketAddr::new(local.ip(), shared.workers[worker].port),
+    };
+    write.write_all(format!("{accepted}\n").as_bytes()).await?;
+    write.flush().await?;
+    let ticket = accepted.ticket;
+    let challenge = shared.hub.challenge();
+    shared.hub.subscribe(admission, &request, |rx| {
+        shared.attach(worker, &request, ticket.clone(), challenge, rx, write)
+    });
+    Ok(())
+}
+
+async fn split(
+    stream: TcpStream,
+    acceptor: Option<&TlsAcceptor>,
+) -> std::io::Result<(ControlReader, ControlWriter)> {
+    match acceptor {
+        Some(acceptor) => {
+            let stream = tokio::time::timeout(COMMAND_TIMEOUT, acceptor.accept(stream))
+                .await
+                .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+            let (read, write) = tokio::io::split(stream);
+            Ok((Box::new(read), Box::new(write)))
+        }
+        None => {
+            let (read, write) = stream.into_split();
+            Ok((Box::new(read), Box::new(write)))
+        }
+    }
+}
+
+async fn read_command(
+    reader: &mut BufReader<ControlReader>,
+    buffer: &mut String,
+) -> std::io::Result<usize> {
+    tokio::time::timeout(COMMAND_TIMEOUT, reader.read_line(buffer))
+        .await
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))?
+}
+
+async fn send(write: &mut ControlWriter, line: &str) -> std::io::Result<()> {
+    write.write_all(line.as_bytes()).await?;
+    write.flush().await
+}
+
+impl Shared {
+    fn attach(
+        &self,
+        index: usize,
+        request: &StreamRequest,
+        ticket: StreamTicket,
+        challenge: Option<String>,
+        rx: QueueReceiver,
+        control: ControlWriter,
+    ) -> AsyncWaker {
+        let worker = self.workers[index].clone();
+        let liveness = Arc::new(Mutex::new(Presence {
+            last_seen: Instant::now(),
+            proof: None,
+            verified: challenge.is_none(),
+        }));
+        let mut state = StreamState::new(&request.options, self.hub.config.heartbeat_interval);
+        if let Some(nonce) = challenge {
+            state.require_proof(nonce);
+        }
+        if let Some(rate) = self.hub.config.max_rate {
+            state.limit_rate(rate);
+        }
+        if let Ok(mut pings) = worker.pings.lock() {
+            pings.insert(ticket.id, (ticket.token.clone(), liveness.clone()));
+        }
+        let notify = Arc::new(Notify::new());
+        let task = StreamTask {
+            worker,
+            id: ticket.id,
+            addr: request.addr,
+            state,
+            rx,
+            notify: notify.clone(),
+            liveness,
+            keepalive: self.hub.config.keepalive_timeout,
+            control: Some(control),
+        };
+        let handle = tokio::spawn(task.run(self.closing.clone()));
+        if let Ok(mut streams) = self.streams.lock() {
+            streams.retain(|handle| !handle.is_finished());
+            streams.push(handle);
+        }
+        AsyncWaker(notify)
+    }
+}
+
+impl UdpWorker {
+    fn touch(&self, ticket: &StreamTicket) -> bool {
+        let Ok(pings) = self.pings.lock() else {
+            return false;
+        };
+        match pings.get(&ticket.id) {
+            Some((token, liveness)) if *token == ticket.token => {
+                if let Ok(mut presence) = liveness.lock()
+                    && presence.verified
+                {
+                    presence.last_seen = Instant::now();
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn prove(&self, id: u64, nonce: String) -> bool {
+        let Ok(pings) = self.pings.lock() else {
+            return false;
+        };
+        match pings.get(&id).map(|(_, liveness)| liveness.lock()) {
+            Some(Ok(mut presence)) => {
+                presence.proof = Some(nonce);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn forget(&self, id: u64) {
+        if let Ok(mut pings) = self.pings.lock() {
+            pings.remove(&id);
+        }
+    }
+}
+
+async fn receive_pings(worker: Arc<UdpWorker>) {
+    let mut buf = [0u8; 256];
+    loop {
+        match worker.socket.recv_from(&mut buf).await {
+            Ok((n, src)) => {
+                if let Some((id, nonce)) = proof_nonce(&buf[..n]) {
+                    if !worker.prove(id, nonce) {
+                        debug!("rejected proof from {src}");
+                    }
+                    continue;
+                }
+                match ping_ticket(&buf[..n]) {
+                    Some(ticket) if worker.touch(&ticket) => {
+                        let _ = worker.socket.send_to(ticket.pong().as_bytes(), src).await;
+                    }
+                    _ => debug!("rejected ping from {src}"),
+                }
+            }
+            Err(err) => debug!("udp recv error: {err}"),
+        }
+    }
+}
+
+impl StreamTask {
+    async fn run(mut self, closing: Shutdown) {
+        info!("stream {} start {}", self.id, self.addr);
+        let mut pending = false;
+        loop {
+            if closing.is_triggered() {
+                self.close().await;
+                break;
+            }
+            if !self.is_alive() {
+                info!("stream {} stop {}", self.id, self.addr);
+                break;
+            }
+            if pending {
+                tokio::task::yield_now().await;
+            } else {
+                let wait = self.state.poll_interval(DISPATCH_TIMEOUT);
+                let _ = tokio::time::timeout(wait, self.notify.notified()).await;
+            }
+            let (outgoing, status) = self.state.drain(&self.rx);
+            self.send(&outgoing).await;
+            match status {
+                Drain::Idle => pending = false,
+                Drain::Pending => pending = true,
+                Drain::Closed => {
+                    info!("stream {} stop {}", self.id, self.addr);
+                    break;
+                }
+            }
+        }
+        self.worker.forget(self.id);
+    }
+
+    fn is_alive(&mut self) -> bool {
+        let Ok(mut presence) = self.liveness.lock() else {
+            return false;
+        };
+        if let Some(nonce) = presence.proof.take()
+            && self.state.verify(&nonce)
+        {
+            presence.last_seen = Instant::now();
+            presence.verified = true;
+            info!("stream {} verified {}", self.id, self.addr);
+        }
+        presence.last_seen.elapsed() <= self.keepalive
+    }
+
+    async fn send(&self, events: &[MarketEvent]) {
+        for event in events {
+            let payload = event.to_string();
+            let _ = self
+                .worker
+                .socket
+                .send_to(payload.as_bytes(), self.addr)
+                .await;
+        }
+    }
+
+    async fn close(&mut self) {
+        let mut outgoing = self.state.finish(&self.rx);
+        outgoing.push(MarketEvent::Control(ControlEvent::shutdown(
+            SHUTDOWN_REASON,
+        )));
+        self.send(&outgoing).await;
+        if let Some(control) = self.control.as_mut() {
+            let _ = control.write_all(BYE).await;
+            let _ = control.flush().await;
+        }
+        info!("stream {} closed {}", self.id, self.addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quote_core::{ControlKind, QuoteGenerator, parse_accepted};
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn streams_quotes_and_shuts_down() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = listener.local_addr().unwrap();
+        let source = Box::new(QuoteGenerator::default());
+        let shutdown = Shutdown::new();
+        let hub = Arc::new(Hub::new(ServerConfig::default()));
+        let server = tokio::spawn(serve(listener, hub, source, shutdown.clone()));
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let command = format!("STREAM udp://{} AAPL\n", client.local_addr().unwrap());
+        let mut control = BufReader::new(TcpStream::connect(server_addr).await.unwrap());
+        control.write_all(command.as_bytes()).await.unwrap();
+        let mut reply = String::new();
+        control.read_line(&mut reply).await.unwrap();
+        let accepted = parse_accepted(&reply).unwrap();
+
+        let mut buf = [0u8; 2048];
+        let recv = client.recv_from(&mut buf);
+        let (n, udp_server) = tokio::time::timeout(Duration::from_secs(3), recv)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(udp_server, accepted.endpoint);
+        let event = MarketEvent::from_string(std::str::from_utf8(&buf[..n]).unwrap());
+        let Some(MarketEvent::Control(ControlEvent {
+            control: ControlKind::Challenge,
+            nonce: Some(nonce),
+            ..
+        })) = event
+        else {
+            panic!("expected a challenge, got {event:?}");
+        };
+        let proof = accepted.ticket.proof(&nonce);
+        client.send_to(proof.as_bytes(), udp_server).await.unwrap();
+        let recv = client.recv_from(&mut buf);
+        let (n, _) = tokio::time::timeout(Duration::from_secs(3), recv)
+            .await
+            .unwrap()
+            .unwrap();
+        let event = MarketEvent::from_string(std::str::from_utf8(&buf[..n]).unwrap()).unwrap();
+        assert_eq!(event.ticker(), Some("AAPL"));
+
+        let ping = accepted.ticket.ping();
+        client.send_to(ping.as_bytes(), udp_server).await.unwrap();
+        let pong = async {
+            loop {
+                let (n, _) = client.recv_from(&mut buf).await.unwrap();
+                if &buf[..n] == accepted.ticket.pong().as_bytes() {
+                    break;
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(3), pong)
+            .await
+            .unwrap();
+
+        shutdown.trigger();
+        let last = async {
+            loop {
+                let (n, _) = client.recv_from(&mut buf).await.unwrap();
+                let payload = std::str::from_utf8(&buf[..n]).unwrap();
+                if let Some(MarketEvent::Control(control)) = MarketEvent::from_string(payload)
+                    && control.control == ControlKind::Shutdown
+                {
+                    break control;
+                }
+            }
+        };
+        let last = tokio::time::timeout(Duration::from_secs(3), last)
+            .await
+            .unwrap();
+        assert_eq!(last.reason.as_deref(), Some(SHUTDOWN_REASON));
+        let mut bye = String::new();
+        control.read_line(&mut bye).await.unwrap();
+        assert_eq!(bye, "BYE\n");
+        let result = tokio::time::timeout(Duration::from_secs(3), server)
+            .await
+            .unwrap();
+        assert!(result.unwrap().is_ok());
+    }
+}
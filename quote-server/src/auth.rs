@@ -0,0 +1,167 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use quote_core::{AuthRequest, ProtocolError, StreamRequest};
+
+use crate::config::ConfigError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    BadRequest(ProtocolError),
+    TooManyClients,
+    IpLimit(usize),
+    TooManyTickers(usize),
+    TargetNotAllowed,
+    AuthRequired,
+    AuthFailed,
+    NotEntitled(String),
+    StreamLimit(usize),
+    DepthUnavailable,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::BadRequest(err) => write!(f, "BAD_REQUEST {err}"),
+            Rejection::TooManyClients => write!(f, "TOO_MANY_CLIENTS"),
+            Rejection::IpLimit(max) => write!(f, "IP_LIMIT {max}"),
+            Rejection::TooManyTickers(max) => write!(f, "TOO_MANY_TICKERS {max}"),
+            Rejection::TargetNotAllowed => write!(f, "TARGET_NOT_ALLOWED"),
+            Rejection::AuthRequired => write!(f, "AUTH_REQUIRED"),
+            Rejection::AuthFailed => write!(f, "AUTH_FAILED"),
+            Rejection::NotEntitled(ticker) => write!(f, "NOT_ENTITLED {ticker}"),
+            Rejection::StreamLimit(max) => write!(f, "STREAM_LIMIT {max}"),
+            Rejection::DepthUnavailable => write!(f, "DEPTH_UNAVAILABLE"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct User {
+    token_sha256: String,
+    tickers: Option<HashSet<String>>,
+    max_streams: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Credentials {
+    users: HashMap<String, User>,
+}
+
+impl Credentials {
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let data = fs::read_to_string(path)?;
+        data.parse()
+    }
+
+    pub fn authenticate(&self, auth: &AuthRequest) -> Result<(), Rejection> {
+        let user = self.users.get(&auth.user).ok_or(Rejection::AuthFailed)?;
+        let hash = hash_token(&auth.token);
+        if constant_eq(hash.as_bytes(), user.token_sha256.as_bytes()) {
+            Ok(())
+        } else {
+            Err(Rejection::AuthFailed)
+        }
+    }
+
+    pub fn entitle(
+        &self,
+        user: &str,
+        request: &StreamRequest,
+        active: usize,
+    ) -> Result<(), Rejection> {
+        let user = self.users.get(user).ok_or(Rejection::AuthFailed)?;
+        if let Some(allowed) = &user.tickers
+            && let Some(ticker) = request.tickers.iter().find(|t| !allowed.contains(*t))
+        {
+            return Err(Rejection::NotEntitled(ticker.clone()));
+        }
+        match user.max_streams {
+            Some(max) if active >= max => Err(Rejection::StreamLimit(max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for Credentials {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut credentials: Credentials =
+            toml::from_str(s).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        for user in credentials.users.values_mut() {
+            user.token_sha256.make_ascii_lowercase();
+            if let Some(tickers) = user.tickers.take() {
+                user.tickers = Some(tickers.iter().map(|t| t.to_uppercase()).collect());
+            }
+        }
+        Ok(credentials)
+    }
+}
+
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn constant_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use quote_core::parse_command;
+
+    use super::*;
+
+    #[test]
+    fn authenticates_and_entitles_users() {
+        let raw = format!(
+            r#"
+            [users.alice]
+            token-sha256 = "{}"
+            tickers = ["aapl", "MSFT"]
+            max-streams = 1
+            "#,
+            hash_token("s3cret").to_uppercase()
+        );
+        let credentials: Credentials = raw.parse().unwrap();
+        let auth = |user: &str, token: &str| AuthRequest {
+            user: user.to_string(),
+            token: token.to_string(),
+        };
+        assert_eq!(credentials.authenticate(&auth("alice", "s3cret")), Ok(()));
+        assert_eq!(
+            credentials.authenticate(&auth("alice", "guess")),
+            Err(Rejection::AuthFailed)
+        );
+        assert_eq!(
+            credentials.authenticate(&auth("bob", "s3cret")),
+            Err(Rejection::AuthFailed)
+        );
+
+        let request = parse_command("STREAM udp://127.0.0.1:9999 AAPL,MSFT").unwrap();
+        assert_eq!(credentials.entitle("alice", &request, 0), Ok(()));
+        assert_eq!(
+            credentials.entitle("alice", &request, 1),
+            Err(Rejection::StreamLimit(1))
+        );
+        let request = parse_command("STREAM udp://127.0.0.1:9999 AAPL,TSLA").unwrap();
+        assert_eq!(
+            credentials.entitle("alice", &request, 0),
+            Err(Rejection::NotEntitled("TSLA".to_string()))
+        );
+
+        assert!("[users.bob]\ntickers = []".parse::<Credentials>().is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ipnet::IpNet;
+use serde::{Deserialize, Deserializer};
+
+use quote_core::{OverflowPolicy, parse_duration};
+
+use crate::auth::Credentials;
+
+pub const DEFAULT_BIND_ADDR: &str = "127.0.0.1:7878";
+pub const DEFAULT_SOURCE_QUEUE: usize = 4096;
+pub const DEFAULT_CLIENT_QUEUE: usize = 1024;
+pub const DEFAULT_UDP_WORKERS: usize = 1;
+pub const DEFAULT_KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub source_queue: usize,
+    pub client_queue: usize,
+    pub overflow: OverflowPolicy,
+    pub udp_workers: usize,
+    pub keepalive_timeout: Duration,
+    pub heartbeat_interval: Duration,
+    pub max_clients: Option<usize>,
+    pub max_streams_per_ip: Option<usize>,
+    pub max_tickers: Option<usize>,
+    pub max_rate: Option<u32>,
+    pub depth: bool,
+    pub credentials: Option<Arc<Credentials>>,
+    pub udp_allow: Vec<IpNet>,
+    pub udp_proof: bool,
+    pub tls: Option<Arc<rustls::ServerConfig>>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            source_queue: DEFAULT_SOURCE_QUEUE,
+            client_queue: DEFAULT_CLIENT_QUEUE,
+            overflow: OverflowPolicy::default(),
+            udp_workers: DEFAULT_UDP_WORKERS,
+            keepalive_timeout: DEFAULT_KEEPALIVE_TIMEOUT,
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            max_clients: None,
+            max_streams_per_ip: None,
+            max_tickers: None,
+            max_rate: None,
+            depth: true,
+            credentials: None,
+            udp_allow: Vec::new(),
+            udp_proof: true,
+            tls: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("unknown log format: {other}")),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FileConfig {
+    pub bind: Option<String>,
+    #[serde(deserialize_with = "duration")]
+    pub tick_interval: Option<Duration>,
+    #[serde(deserialize_with = "duration")]
+    pub keepalive_timeout: Option<Duration>,
+    #[serde(deserialize_with = "duration")]
+    pub heartbeat_interval: Option<Duration>,
+    pub max_clients: Option<usize>,
+    pub max_streams_per_ip: Option<usize>,
+    pub max_tickers: Option<usize>,
+    pub max_rate: Option<u32>,
+    pub universe: Option<PathBuf>,
+    pub credentials: Option<PathBuf>,
+    pub udp_allow: Option<Vec<IpNet>>,
+    pub udp_proof: Option<bool>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub tls_client_ca: Option<PathBuf>,
+    pub log_format: Option<LogFormat>,
+    pub client_queue: Option<usize>,
+    #[serde(deserialize_with = "policy")]
+    pub overflow: Option<OverflowPolicy>,
+    pub udp_workers: Option<usize>,
+    pub book_depth: Option<usize>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "io error: {err}"),
+            ConfigError::Parse(err) => write!(f, "parse error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(err: std::io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+impl FileConfig {
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let data = fs::read_to_string(path)?;
+        data.parse()
+    }
+}
+
+impl FromStr for FileConfig {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s).map_err(|e| ConfigError::Parse(e.to_string()))
+    }
+}
+
+pub fn load_universe(path: &Path) -> Result<Vec<String>, ConfigError> {
+    let data = fs::read_to_string(path)?;
+    let tickers = data
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_uppercase)
+        .collect::<Vec<_>>();
+    if tickers.is_empty() {
+        return Err(ConfigError::Parse(format!(
+            "no tickers in {}",
+            path.display()
+        )));
+    }
+    Ok(tickers)
+}
+
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_duration(&value)
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid duration: {value}")))
+}
+
+fn policy<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<OverflowPolicy>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    value.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_file_config() {
+        let raw = r#"
+            bind = "0.0.0.0:9000"
+            tick-interval = "250ms"
+            keepalive-timeout = "10s"
+            max-clients = 500
+            max-streams-per-ip = 4
+            max-rate = 200
+            log-format = "json"
+            overflow = "conflate"
+            udp-allow = ["10.0.0.0/8", "192.168.1.7/32"]
+            udp-proof = false
+        "#;
+        let config: FileConfig = raw.parse().unwrap();
+        assert_eq!(config.bind.as_deref(), Some("0.0.0.0:9000"));
+        assert_eq!(config.tick_interval, Some(Duration::from_millis(250)));
+        assert_eq!(config.keepalive_timeout, Some(Duration::from_secs(10)));
+        assert_eq!(config.max_clients, Some(500));
+        assert_eq!(config.max_streams_per_ip, Some(4));
+        assert_eq!(config.max_tickers, None);
+        assert_eq!(config.max_rate, Some(200));
+        assert_eq!(config.log_format, Some(LogFormat::Json));
+        assert_eq!(config.overflow, Some(OverflowPolicy::Conflate));
+        assert!(config.universe.is_none());
+        let allow = config.udp_allow.unwrap();
+        assert!(allow[0].contains(&"10.1.2.3".parse::<std::net::IpAddr>().unwrap()));
+        assert_eq!(config.udp_proof, Some(false));
+
+        assert!("tick-interval = \"soon\"".parse::<FileConfig>().is_err());
+        assert!("unknown = 1".parse::<FileConfig>().is_err());
+    }
+
+    #[test]
+    fn loads_universe() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("universe.txt");
+        fs::write(&path, "aapl\n# comment\n\nmsft\n").unwrap();
+        assert_eq!(load_universe(&path).unwrap(), vec!["AAPL", "MSFT"]);
+        fs::write(&path, "\n").unwrap();
+        assert!(load_universe(&path).is_err());
+    }
+}
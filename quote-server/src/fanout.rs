@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::io::{ErrorKind, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{Receiver, Sender, bounded, select, unbounded};
+use log::{debug, error, info};
+
+use quote_core::{ControlEvent, MarketEvent, StreamRequest, StreamTicket};
+
+use crate::hub::Wake;
+use crate::queue::QueueReceiver;
+use crate::shutdown::{BYE, SHUTDOWN_REASON};
+use crate::stream::{DISPATCH_TIMEOUT, Drain, StreamState, ping_ticket, proof_nonce};
+use crate::tls::Control;
+
+pub const UDP_BIND_ADDR: &str = "0.0.0.0:0";
+
+#[derive(Clone)]
+pub struct Waker(Sender<()>);
+
+impl Wake for Waker {
+    fn wake(&self) {
+        let _ = self.0.try_send(());
+    }
+}
+
+enum Command {
+    Attach(Box<StreamSlot>),
+    Shutdown,
+}
+
+struct Worker {
+    commands: Sender<Command>,
+    wake: Sender<()>,
+    port: u16,
+}
+
+pub struct FanoutPool {
+    workers: Vec<Worker>,
+    handles: Mutex<Vec<thread::JoinHandle<()>>>,
+    next: AtomicUsize,
+    heartbeat: Duration,
+    rate: Option<u32>,
+}
+
+struct StreamSlot {
+    ticket: StreamTicket,
+    addr: SocketAddr,
+    state: StreamState,
+    rx: QueueReceiver,
+    control: Option<Control>,
+    last_ping: Instant,
+}
+
+impl FanoutPool {
+    pub fn spawn(
+        workers: usize,
+        keepalive: Duration,
+        heartbeat: Duration,
+        rate: Option<u32>,
+    ) -> std::io::Result<Self> {
+        let mut pool = Vec::new();
+        let mut handles = Vec::new();
+        for _ in 0..workers.max(1) {
+            let socket = UdpSocket::bind(UDP_BIND_ADDR)?;
+            socket.set_nonblocking(true)?;
+            let port = socket.local_addr()?.port();
+            info!("udp worker on {}", socket.local_addr()?);
+            let (commands_tx, commands_rx) = unbounded();
+            let (wake_tx, wake_rx) = bounded(1);
+            handles.push(thread::spawn(move || {
+                run_worker(socket, commands_rx, wake_rx, keepalive)
+            }));
+            pool.push(Worker {
+                commands: commands_tx,
+                wake: wake_tx,
+                port,
+            });
+        }
+        Ok(FanoutPool {
+            workers: pool,
+            handles: Mutex::new(handles),
+            next: AtomicUsize::new(0),
+            heartbeat,
+            rate,
+        })
+    }
+
+    pub fn assign(&self) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed) % self.workers.len()
+    }
+
+    pub fn udp_port(&self, worker: usize) -> u16 {
+        self.workers[worker].port
+    }
+
+    pub fn attach(
+        &self,
+        index: usize,
+        request: &StreamRequest,
+        ticket: StreamTicket,
+        challenge: Option<String>,
+        rx: QueueReceiver,
+        control: Option<Control>,
+    ) -> Waker {
+        let worker = &self.workers[index];
+        let mut state = StreamState::new(&request.options, self.heartbeat);
+        if let Some(nonce) = challenge {
+            state.require_proof(nonce);
+        }
+        if let Some(rate) = self.rate {
+            state.limit_rate(rate);
+        }
+        let slot = StreamSlot {
+            ticket,
+            addr: request.addr,
+            state,
+            rx,
+            control,
+            last_ping: Instant::now(),
+        };
+        if worker
+            .commands
+            .send(Command::Attach(Box::new(slot)))
+            .is_err()
+        {
+            error!("udp worker {index} is gone");
+        }
+        let waker = Waker(worker.wake.clone());
+        waker.wake();
+        waker
+    }
+
+    pub fn shutdown(&self) {
+        for worker in &self.workers {
+            let _ = worker.commands.send(Command::Shutdown);
+        }
+        let handles = match self.handles.lock() {
+            Ok(mut handles) => std::mem::take(&mut *handles),
+            Err(_) => return,
+        };
+        for handle in handles {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn run_worker(
+    socket: UdpSocket,
+    commands: Receiver<Command>,
+    wake: Receiver<()>,
+    keepalive: Duration,
+) {
+    let mut table: HashMap<u64, StreamSlot> = HashMap::new();
+    let mut pending = false;
+    loop {
+        let timeout = if pending {
+            Duration::ZERO
+        } else {
+            table
+                .values()
+                .map(|slot| slot.state.poll_interval(DISPATCH_TIMEOUT))
+                .min()
+                .unwrap_or(DISPATCH_TIMEOUT)
+        };
+        let mut incoming = Vec::new();
+        select! {
+            recv(commands) -> command => incoming.push(command.unwrap_or(Command::Shutdown)),
+            recv(wake) -> _ => {}
+            default(timeout) => {}
+        }
+        incoming.extend(commands.try_iter());
+        for command in incoming {
+            match command {
+                Command::Attach(slot) => {
+                    info!("stream {} start {}", slot.ticket.id, slot.addr);
+                    table.insert(slot.ticket.id, *slot);
+                }
+                Command::Shutdown => {
+                    close_all(&socket, table);
+                    return;
+                }
+            }
+        }
+        receive_pings(&socket, &mut table);
+        pending = false;
+        table.retain(|_, slot| match service(&socket, slot, keepalive) {
+            Drain::Idle => true,
+            Drain::Pending => {
+                pending = true;
+                true
+            }
+            Drain::Closed => false,
+        });
+    }
+}
+
+fn close_all(socket: &UdpSocket, table: HashMap<u64, StreamSlot>) {
+    let last = MarketEvent::Control(ControlEvent::shutdown(SHUTDOWN_REASON));
+    for mut slot in table.into_values() {
+        for event in slot.state.finish(&slot.rx) {
+            send_event(socket, slot.addr, &event);
+        }
+        send_event(socket, slot.addr, &last);
+        if let Some(control) = slot.control.as_mut() {
+            let _ = control.write_all(BYE);
+            let _ = control.flush();
+        }
+        info!("stream {} closed {}", slot.ticket.id, slot.addr);
+    }
+}
+
+fn receive_pings(socket: &UdpSocket, table: &mut HashMap<u64, StreamSlot>) {
+    let mut buf = [0u8; 256];
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((n, src)) => {
+                if let Some((id, nonce)) = proof_nonce(&buf[..n]) {
+                    let verified = table.get_mut(&id).is_some_and(|slot| {
+                        let verified = slot.state.verify(&nonce);
+                        if verified {
+                            slot.last_ping = Instant::now();
+                            info!("stream {id} verified {}", slot.addr);
+                        }
+                        verified
+                    });
+                    if !verified {
+                        debug!("rejected proof from {src}");
+                    }
+                    continue;
+                }
+                let slot = ping_ticket(&buf[..n]).and_then(|ticket| {
+                    table
+                        .get_mut(&ticket.id)
+                        .filter(|slot| slot.ticket.token == ticket.token)
+                });
+                match slot {
+                    Some(slot) => {
+                        if slot.state.is_verified() {
+                            slot.last_ping = Instant::now();
+                        }
+                        let _ = socket.send_to(slot.ticket.pong().as_bytes(), src);
+                    }
+                    None => debug!("rejected ping from {src}"),
+                }
+            }
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+            Err(err) => {
+                debug!("udp recv error: {err}");
+                break;
+            }
+        }
+    }
+}
+
+fn service(socket: &UdpSocket, slot: &mut StreamSlot, keepalive: Duration) -> Drain {
+    if slot.last_ping.elapsed() > keepalive {
+        info!("stream {} stop {}", slot.ticket.id, slot.addr);
+        return Drain::Closed;
+    }
+    let (outgoing, status) = slot.state.drain(&slot.rx);
+    for event in &outgoing {
+        send_event(socket, slot.addr, event);
+    }
+    if status == Drain::Closed {
+        info!("stream {} stop {}", slot.ticket.id, slot.addr);
+    }
+    status
+}
+
+fn send_event(socket: &UdpSocket, addr: SocketAddr, event: &MarketEvent) {
+    let payload = event.to_string();
+    let _ = socket.send_to(payload.as_bytes(), addr);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use quote_core::{OverflowPolicy, StockQuote, StreamOptions};
+
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::metrics::Metrics;
+    use crate::queue::{Delivery, client_queue};
+
+    #[test]
+    fn challenges_streams_checks_pings_and_shuts_down() {
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let request = StreamRequest {
+            addr: client.local_addr().unwrap(),
+            tickers: vec!["AAPL".to_string()],
+            options: StreamOptions::default(),
+        };
+        let (queue, rx) = client_queue(
+            request.addr,
+            16,
+            OverflowPolicy::default(),
+            Arc::new(Metrics::default()),
+        );
+        let pool = FanoutPool::spawn(1, Duration::from_secs(5), Duration::ZERO, None).unwrap();
+        let ticket = StreamTicket {
+            id: 1,
+            token: "secret".to_string(),
+        };
+        let worker = pool.assign();
+        let waker = pool.attach(
+            worker,
+            &request,
+            ticket.clone(),
+            Some("c0ffee".to_string()),
+            rx,
+            None,
+        );
+        let mut buf = [0u8; 1024];
+        let (n, server) = client.recv_from(&mut buf).unwrap();
+        assert_eq!(server.port(), pool.udp_port(worker));
+        let payload = std::str::from_utf8(&buf[..n]).unwrap();
+        assert!(matches!(
+            MarketEvent::from_string(payload),
+            Some(MarketEvent::Control(ControlEvent { nonce: Some(nonce), .. })) if nonce == "c0ffee"
+        ));
+        client
+            .send_to(ticket.proof("c0ffee").as_bytes(), server)
+            .unwrap();
+
+        let quote = MarketEvent::Quote(StockQuote {
+            ticker: "AAPL".to_string(),
+            price: 100.0,
+            volume: 1,
+            timestamp: 1,
+            seq: None,
+        });
+        assert_eq!(queue.push(quote.clone()), Delivery::Queued);
+        waker.wake();
+
+        let (n, _) = client.recv_from(&mut buf).unwrap();
+        let payload = std::str::from_utf8(&buf[..n]).unwrap();
+        assert_eq!(MarketEvent::from_string(payload).unwrap(), quote);
+
+        client.send_to(b"PING 1 forged", server).unwrap();
+        client.send_to(ticket.ping().as_bytes(), server).unwrap();
+        let (n, _) = client.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..n], ticket.pong().as_bytes());
+
+        pool.shutdown();
+        let (n, _) = client.recv_from(&mut buf).unwrap();
+        let payload = std::str::from_utf8(&buf[..n]).unwrap();
+        assert!(matches!(
+            MarketEvent::from_string(payload),
+            Some(MarketEvent::Control(_))
+        ));
+    }
+
+    #[test]
+    fn pings_do_not_keep_unverified_streams_alive() {
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_millis(30)))
+            .unwrap();
+        let request = StreamRequest {
+            addr: client.local_addr().unwrap(),
+            tickers: vec!["AAPL".to_string()],
+            options: StreamOptions::default(),
+        };
+        let (_queue, rx) = client_queue(
+            request.addr,
+            16,
+            OverflowPolicy::default(),
+            Arc::new(Metrics::default()),
+        );
+        let pool = FanoutPool::spawn(1, Duration::from_millis(200), Duration::ZERO, None).unwrap();
+        let ticket = StreamTicket {
+            id: 1,
+            token: "secret".to_string(),
+        };
+        let worker = pool.assign();
+        pool.attach(
+            worker,
+            &request,
+            ticket.clone(),
+            Some("c0ffee".to_string()),
+            rx,
+            None,
+        );
+        let mut buf = [0u8; 1024];
+        let server = (Ipv4Addr::LOCALHOST, pool.udp_port(worker));
+        for _ in 0..8 {
+            client.send_to(ticket.ping().as_bytes(), server).unwrap();
+            thread::sleep(Duration::from_millis(50));
+        }
+        while client.recv_from(&mut buf).is_ok() {}
+
+        client
+            .set_read_timeout(Some(Duration::from_millis(300)))
+            .unwrap();
+        client.send_to(ticket.ping().as_bytes(), server).unwrap();
+        assert!(client.recv_from(&mut buf).is_err());
+        pool.shutdown();
+    }
+}
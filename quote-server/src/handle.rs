@@ -0,0 +1,371 @@
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::thread;
+
+use quote_core::{OverflowPolicy, QuoteGenerator, QuoteSource, StreamOptions};
+
+use crate::config::{DEFAULT_BIND_ADDR, ServerConfig};
+use crate::hub::StreamDirectory;
+use crate::metrics::Metrics;
+use crate::shutdown::Shutdown;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamInfo {
+    pub id: u64,
+    pub user: Option<String>,
+    pub addr: SocketAddr,
+    pub tickers: Vec<String>,
+    pub options: StreamOptions,
+    pub policy: OverflowPolicy,
+    pub dropped: u64,
+}
+
+pub struct QuoteServerBuilder {
+    bind: String,
+    config: ServerConfig,
+    source: Option<Box<dyn QuoteSource>>,
+    async_runtime: bool,
+}
+
+pub struct QuoteServer {
+    local_addr: SocketAddr,
+    shutdown: Shutdown,
+    directory: Arc<dyn StreamDirectory>,
+    thread: Option<thread::JoinHandle<std::io::Result<()>>>,
+}
+
+impl QuoteServerBuilder {
+    pub fn bind(mut self, addr: &str) -> Self {
+        self.bind = addr.to_string();
+        self
+    }
+
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn source(mut self, source: Box<dyn QuoteSource>) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    #[cfg(feature = "async")]
+    pub fn async_runtime(mut self, enabled: bool) -> Self {
+        self.async_runtime = enabled;
+        self
+    }
+
+    pub fn start(self) -> std::io::Result<QuoteServer> {
+        let listener = TcpListener::bind(&self.bind)?;
+        let local_addr = listener.local_addr()?;
+        let source = self
+            .source
+            .unwrap_or_else(|| Box::new(QuoteGenerator::default()));
+        let shutdown = Shutdown::new();
+        let (directory, thread) = if self.async_runtime {
+            spawn_async(listener, self.config, source, shutdown.clone())
+        } else {
+            crate::server::spawn(listener, self.config, source, shutdown.clone())
+        };
+        Ok(QuoteServer {
+            local_addr,
+            shutdown,
+            directory,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Default for QuoteServerBuilder {
+    fn default() -> Self {
+        QuoteServerBuilder {
+            bind: DEFAULT_BIND_ADDR.to_string(),
+            config: ServerConfig::default(),
+            source: None,
+            async_runtime: false,
+        }
+    }
+}
+
+impl QuoteServer {
+    pub fn builder() -> QuoteServerBuilder {
+        QuoteServerBuilder::default()
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn active_streams(&self) -> Vec<StreamInfo> {
+        self.directory.streams()
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.directory.metrics()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+
+    pub fn shutdown(mut self) -> std::io::Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> std::io::Result<()> {
+        self.shutdown.trigger();
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .unwrap_or_else(|_| Err(std::io::Error::other("server thread panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for QuoteServer {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(feature = "async")]
+fn spawn_async(
+    listener: TcpListener,
+    config: ServerConfig,
+    source: Box<dyn QuoteSource>,
+    shutdown: Shutdown,
+) -> (
+    Arc<dyn StreamDirectory>,
+    thread::JoinHandle<std::io::Result<()>>,
+) {
+    crate::async_server::spawn(listener, config, source, shutdown)
+}
+
+#[cfg(not(feature = "async"))]
+fn spawn_async(
+    listener: TcpListener,
+    config: ServerConfig,
+    source: Box<dyn QuoteSource>,
+    shutdown: Shutdown,
+) -> (
+    Arc<dyn StreamDirectory>,
+    thread::JoinHandle<std::io::Result<()>>,
+) {
+    crate::server::spawn(listener, config, source, shutdown)
+}
+
+#[cfg(test)]
+pub(crate) fn test_servers(
+    config: &ServerConfig,
+) -> impl Iterator<Item = (bool, QuoteServer)> + '_ {
+    [false, true].into_iter().map(|async_runtime| {
+        let builder = QuoteServer::builder()
+            .bind("127.0.0.1:0")
+            .config(config.clone());
+        #[cfg(feature = "async")]
+        let builder = builder.async_runtime(async_runtime);
+        (async_runtime, builder.start().unwrap())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpStream, UdpSocket};
+    use std::sync::Barrier;
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, Instant};
+
+    use quote_core::{StreamAccepted, parse_accepted};
+
+    use super::*;
+    use crate::auth::{Credentials, hash_token};
+
+    fn subscribe(server: &QuoteServer, udp: &UdpSocket) -> (StreamAccepted, BufReader<TcpStream>) {
+        let mut control = TcpStream::connect(server.local_addr()).unwrap();
+        let command = format!(
+            "STREAM udp://{} AAPL,MSFT DEPTH\n",
+            udp.local_addr().unwrap()
+        );
+        control.write_all(command.as_bytes()).unwrap();
+        let mut control = BufReader::new(control);
+        let mut reply = String::new();
+        control.read_line(&mut reply).unwrap();
+        (parse_accepted(&reply).unwrap(), control)
+    }
+
+    fn wait_for_streams(server: &QuoteServer, count: usize) -> Vec<StreamInfo> {
+        let deadline = Instant::now() + Duration::from_secs(3);
+        loop {
+            let streams = server.active_streams();
+            if streams.len() == count || Instant::now() > deadline {
+                return streams;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn embeds_server_and_lists_streams() {
+        for (async_runtime, server) in test_servers(&ServerConfig::default()) {
+            assert_ne!(server.local_addr().port(), 0);
+            assert!(server.active_streams().is_empty());
+
+            let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let (accepted, mut control) = subscribe(&server, &udp);
+            let streams = wait_for_streams(&server, 1);
+            assert_eq!(streams.len(), 1, "async={async_runtime}");
+            assert_eq!(streams[0].id, accepted.ticket.id);
+            assert_eq!(streams[0].addr, udp.local_addr().unwrap());
+            assert_eq!(streams[0].tickers, vec!["AAPL", "MSFT"]);
+            assert!(streams[0].options.depth);
+            assert_eq!(streams[0].policy, OverflowPolicy::DropOldest);
+
+            server.shutdown().unwrap();
+            let mut bye = String::new();
+            control.read_line(&mut bye).unwrap();
+            assert_eq!(bye, "BYE\n");
+        }
+    }
+
+    #[test]
+    fn rejects_streams_over_limits() {
+        let config = ServerConfig {
+            max_streams_per_ip: Some(1),
+            max_tickers: Some(2),
+            depth: false,
+            ..ServerConfig::default()
+        };
+        for (async_runtime, server) in test_servers(&config) {
+            let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let request = |tickers: &str| {
+                let mut control = TcpStream::connect(server.local_addr()).unwrap();
+                let command = format!("STREAM udp://{} {tickers}\n", udp.local_addr().unwrap());
+                control.write_all(command.as_bytes()).unwrap();
+                let mut control = BufReader::new(control);
+                let mut reply = String::new();
+                control.read_line(&mut reply).unwrap();
+                (reply.trim().to_string(), control)
+            };
+
+            let (reply, _) = request("AAPL,MSFT,TSLA");
+            assert_eq!(reply, "ERR TOO_MANY_TICKERS 2", "async={async_runtime}");
+            let (reply, _) = request("AAPL DEPTH");
+            assert_eq!(reply, "ERR DEPTH_UNAVAILABLE");
+            let (reply, _control) = request("AAPL,MSFT");
+            assert!(parse_accepted(&reply).is_ok());
+            wait_for_streams(&server, 1);
+            let (reply, _) = request("TSLA");
+            assert_eq!(reply, "ERR IP_LIMIT 1");
+
+            let metrics = server.metrics();
+            assert_eq!(metrics.rejected_streams.load(Ordering::Relaxed), 3);
+            assert_eq!(metrics.rejected_ticker_limit.load(Ordering::Relaxed), 1);
+            assert_eq!(metrics.rejected_ip_limit.load(Ordering::Relaxed), 1);
+            assert_eq!(metrics.rejected_client_limit.load(Ordering::Relaxed), 0);
+            assert_eq!(
+                metrics.rejected_depth_unavailable.load(Ordering::Relaxed),
+                1
+            );
+            server.shutdown().unwrap();
+        }
+    }
+
+    #[test]
+    fn admits_concurrent_streams_up_to_the_limit() {
+        let config = ServerConfig {
+            max_clients: Some(3),
+            ..ServerConfig::default()
+        };
+        for (async_runtime, server) in test_servers(&config) {
+            let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let command = format!("STREAM udp://{} AAPL\n", udp.local_addr().unwrap());
+            let barrier = Barrier::new(12);
+            let replies: Vec<_> = thread::scope(|scope| {
+                let workers: Vec<_> = (0..12)
+                    .map(|_| {
+                        scope.spawn(|| {
+                            let mut control = TcpStream::connect(server.local_addr()).unwrap();
+                            barrier.wait();
+                            control.write_all(command.as_bytes()).unwrap();
+                            let mut control = BufReader::new(control);
+                            let mut reply = String::new();
+                            control.read_line(&mut reply).unwrap();
+                            (reply.trim().to_string(), control)
+                        })
+                    })
+                    .collect();
+                workers
+                    .into_iter()
+                    .map(|worker| worker.join().unwrap())
+                    .collect()
+            });
+
+            let accepted = replies
+                .iter()
+                .filter(|(reply, _)| parse_accepted(reply).is_ok())
+                .count();
+            assert_eq!(accepted, 3, "async={async_runtime}");
+            assert!(
+                replies
+                    .iter()
+                    .all(|(reply, _)| parse_accepted(reply).is_ok()
+                        || reply == "ERR TOO_MANY_CLIENTS")
+            );
+            assert_eq!(wait_for_streams(&server, 3).len(), 3);
+            server.shutdown().unwrap();
+        }
+    }
+
+    #[test]
+    fn authenticates_and_enforces_entitlements() {
+        let credentials: Credentials = format!(
+            "[users.alice]\ntoken-sha256 = \"{}\"\ntickers = [\"AAPL\"]\nmax-streams = 1\n",
+            hash_token("s3cret")
+        )
+        .parse()
+        .unwrap();
+        let config = ServerConfig {
+            credentials: Some(Arc::new(credentials)),
+            ..ServerConfig::default()
+        };
+        for (async_runtime, server) in test_servers(&config) {
+            let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let send = |lines: &[&str]| {
+                let mut control = TcpStream::connect(server.local_addr()).unwrap();
+                let mut reader = BufReader::new(control.try_clone().unwrap());
+                let mut replies = Vec::new();
+                for line in lines {
+                    let line = line.replace("{udp}", &udp.local_addr().unwrap().to_string());
+                    control.write_all(format!("{line}\n").as_bytes()).unwrap();
+                    let mut reply = String::new();
+                    reader.read_line(&mut reply).unwrap();
+                    replies.push(reply.trim().to_string());
+                }
+                (replies, reader)
+            };
+
+            let (replies, _) = send(&["STREAM udp://{udp} AAPL"]);
+            assert_eq!(replies, vec!["ERR AUTH_REQUIRED"], "async={async_runtime}");
+            let (replies, _) = send(&["AUTH alice guess"]);
+            assert_eq!(replies, vec!["ERR AUTH_FAILED"]);
+            let (replies, _) = send(&["AUTH alice s3cret", "STREAM udp://127.0.0.2:9 AAPL"]);
+            assert_eq!(replies, vec!["OK alice", "ERR TARGET_NOT_ALLOWED"]);
+            let (replies, _) = send(&["AUTH alice s3cret", "STREAM udp://{udp} AAPL,TSLA"]);
+            assert_eq!(replies, vec!["OK alice", "ERR NOT_ENTITLED TSLA"]);
+            let (replies, _control) = send(&["AUTH alice s3cret", "STREAM udp://{udp} AAPL"]);
+            assert!(parse_accepted(&replies[1]).is_ok());
+            assert_eq!(
+                wait_for_streams(&server, 1)[0].user.as_deref(),
+                Some("alice")
+            );
+            let (replies, _) = send(&["AUTH alice s3cret", "STREAM udp://{udp} AAPL"]);
+            assert_eq!(replies[1], "ERR STREAM_LIMIT 1");
+            server.shutdown().unwrap();
+        }
+    }
+}
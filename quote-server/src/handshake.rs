@@ -0,0 +1,119 @@
+use std::net::IpAddr;
+
+use log::{info, warn};
+
+use quote_core::{StreamRequest, parse_auth, parse_command};
+
+use crate::auth::Rejection;
+use crate::hub::{Admission, Hub, Wake};
+
+pub enum Step<'a, W> {
+    Reply(String),
+    Reject(String),
+    Accept(Admission<'a, W>, StreamRequest),
+    Close,
+}
+
+pub struct Handshake<'a, W> {
+    hub: &'a Hub<W>,
+    peer: IpAddr,
+    user: Option<String>,
+    first: bool,
+}
+
+impl<'a, W: Wake> Handshake<'a, W> {
+    pub fn new(hub: &'a Hub<W>, peer: IpAddr) -> Self {
+        Handshake {
+            hub,
+            peer,
+            user: None,
+            first: true,
+        }
+    }
+
+    pub fn on_line(&mut self, line: &str) -> Step<'a, W> {
+        let first = std::mem::replace(&mut self.first, false);
+        if first && let Ok(auth) = parse_auth(line) {
+            if let Err(rejection) = self.hub.authenticate(&auth) {
+                return self.reject(&rejection);
+            }
+            info!("authenticated {}", auth.user);
+            let reply = format!("OK {}\n", auth.user);
+            self.user = Some(auth.user);
+            return Step::Reply(reply);
+        }
+        if line.trim().is_empty() {
+            return Step::Close;
+        }
+        let request = match parse_command(line) {
+            Ok(request) => request,
+            Err(err) => return self.reject(&Rejection::BadRequest(err)),
+        };
+        match self.hub.admit(self.user.as_deref(), self.peer, &request) {
+            Ok(admission) => Step::Accept(admission, request),
+            Err(rejection) => self.reject(&rejection),
+        }
+    }
+
+    fn reject(&self, rejection: &Rejection) -> Step<'a, W> {
+        warn!("rejecting stream: {rejection}");
+        self.hub.metrics.record_rejection(rejection);
+        Step::Reject(format!("ERR {rejection}\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::ServerConfig;
+    use crate::auth::{Credentials, hash_token};
+
+    struct Idle;
+
+    impl Wake for Idle {
+        fn wake(&self) {}
+    }
+
+    #[test]
+    fn authenticates_then_admits_one_command() {
+        let credentials: Credentials = format!(
+            "[users.alice]\ntoken-sha256 = \"{}\"\ntickers = [\"AAPL\"]\n",
+            hash_token("s3cret")
+        )
+        .parse()
+        .unwrap();
+        let hub: Hub<Idle> = Hub::new(ServerConfig {
+            credentials: Some(Arc::new(credentials)),
+            ..ServerConfig::default()
+        });
+        let peer = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        let mut handshake = Handshake::new(&hub, peer);
+        assert!(matches!(handshake.on_line("\n"), Step::Close));
+
+        let mut handshake = Handshake::new(&hub, peer);
+        let step = handshake.on_line("STREAM udp://127.0.0.1:9999 AAPL\n");
+        assert!(matches!(step, Step::Reject(reply) if reply == "ERR AUTH_REQUIRED\n"));
+
+        let mut handshake = Handshake::new(&hub, peer);
+        let step = handshake.on_line("AUTH alice s3cret\n");
+        assert!(matches!(step, Step::Reply(reply) if reply == "OK alice\n"));
+        let step = handshake.on_line("AUTH alice s3cret\n");
+        assert!(matches!(step, Step::Reject(reply) if reply.starts_with("ERR BAD_REQUEST")));
+
+        let mut handshake = Handshake::new(&hub, peer);
+        handshake.on_line("AUTH alice s3cret\n");
+        let step = handshake.on_line("STREAM udp://127.0.0.1:9999 AAPL\n");
+        assert!(matches!(step, Step::Accept(_, request) if request.tickers == ["AAPL"]));
+        assert_eq!(hub.metrics.rejected_streams.load(Ordering::Relaxed), 2);
+        assert_eq!(
+            hub.metrics.rejected_auth_required.load(Ordering::Relaxed),
+            1
+        );
+        assert_eq!(hub.metrics.rejected_bad_request.load(Ordering::Relaxed), 1);
+    }
+}
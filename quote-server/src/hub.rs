@@ -0,0 +1,277 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use log::info;
+
+use quote_core::{AuthRequest, MarketEvent, StreamOptions, StreamRequest, StreamTicket};
+
+use crate::auth::Rejection;
+
+use crate::config::ServerConfig;
+use crate::handle::StreamInfo;
+use crate::metrics::Metrics;
+use crate::queue::{ClientQueue, Delivery, QueueReceiver, client_queue};
+use crate::registry::{Registry, SubscriberId};
+
+pub trait Wake {
+    fn wake(&self);
+}
+
+pub trait StreamDirectory: Send + Sync {
+    fn streams(&self) -> Vec<StreamInfo>;
+    fn metrics(&self) -> Arc<Metrics>;
+}
+
+pub struct ClientEntry<W> {
+    id: u64,
+    user: Option<String>,
+    peer: IpAddr,
+    addr: SocketAddr,
+    tickers: Vec<String>,
+    options: StreamOptions,
+    queue: ClientQueue,
+    waker: W,
+}
+
+struct Pending {
+    id: u64,
+    user: Option<String>,
+    peer: IpAddr,
+}
+
+pub struct Admission<'a, W> {
+    hub: &'a Hub<W>,
+    pub ticket: StreamTicket,
+    user: Option<String>,
+    peer: IpAddr,
+}
+
+pub struct Hub<W> {
+    pub config: ServerConfig,
+    pub metrics: Arc<Metrics>,
+    registry: Registry<ClientEntry<W>>,
+    pending: Mutex<Vec<Pending>>,
+    next_id: AtomicU64,
+}
+
+impl<W: Wake> Hub<W> {
+    pub fn new(config: ServerConfig) -> Self {
+        Hub {
+            config,
+            metrics: Arc::new(Metrics::default()),
+            registry: Registry::new(),
+            pending: Mutex::new(Vec::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    fn ticket(&self) -> StreamTicket {
+        StreamTicket {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            token: format!("{:016x}", rand::random::<u64>()),
+        }
+    }
+
+    pub fn subscribe(
+        &self,
+        admission: Admission<'_, W>,
+        request: &StreamRequest,
+        attach: impl FnOnce(QueueReceiver) -> W,
+    ) {
+        let policy = request.options.overflow.unwrap_or(self.config.overflow);
+        let (queue, rx) = client_queue(
+            request.addr,
+            self.config.client_queue,
+            policy,
+            self.metrics.clone(),
+        );
+        let waker = attach(rx);
+        let mut pending = self.lock_pending();
+        self.registry.insert(
+            &request.tickers,
+            ClientEntry {
+                id: admission.ticket.id,
+                user: admission.user.clone(),
+                peer: admission.peer,
+                addr: request.addr,
+                tickers: request.tickers.clone(),
+                options: request.options.clone(),
+                queue,
+                waker,
+            },
+        );
+        pending.retain(|entry| entry.id != admission.ticket.id);
+    }
+
+    pub fn authenticate(&self, auth: &AuthRequest) -> Result<(), Rejection> {
+        match &self.config.credentials {
+            Some(credentials) => credentials.authenticate(auth),
+            None => Ok(()),
+        }
+    }
+
+    pub fn admit(
+        &self,
+        user: Option<&str>,
+        peer: IpAddr,
+        request: &StreamRequest,
+    ) -> Result<Admission<'_, W>, Rejection> {
+        let peer = peer.to_canonical();
+        let target = request.addr.ip().to_canonical();
+        if target != peer && !self.trusted(target) {
+            return Err(Rejection::TargetNotAllowed);
+        }
+        if let Some(max) = self.config.max_tickers
+            && request.tickers.len() > max
+        {
+            return Err(Rejection::TooManyTickers(max));
+        }
+        if request.options.depth && !self.config.depth {
+            return Err(Rejection::DepthUnavailable);
+        }
+        let mut pending = self.lock_pending();
+        self.prune();
+        if self.is_full(&pending) {
+            return Err(Rejection::TooManyClients);
+        }
+        if let Some(max) = self.config.max_streams_per_ip
+            && self.peer_streams(&pending, peer) >= max
+        {
+            return Err(Rejection::IpLimit(max));
+        }
+        if let Some(credentials) = &self.config.credentials {
+            let user = user.ok_or(Rejection::AuthRequired)?;
+            credentials.entitle(user, request, self.user_streams(&pending, user))?;
+        }
+        let admission = Admission {
+            hub: self,
+            ticket: self.ticket(),
+            user: user.map(str::to_string),
+            peer,
+        };
+        pending.push(Pending {
+            id: admission.ticket.id,
+            user: admission.user.clone(),
+            peer,
+        });
+        Ok(admission)
+    }
+
+    fn lock_pending(&self) -> MutexGuard<'_, Vec<Pending>> {
+        self.pending.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub fn challenge(&self) -> Option<String> {
+        self.config
+            .udp_proof
+            .then(|| format!("{:016x}", rand::random::<u64>()))
+    }
+
+    fn trusted(&self, ip: IpAddr) -> bool {
+        self.config.udp_allow.iter().any(|net| net.contains(&ip))
+    }
+
+    fn user_streams(&self, pending: &[Pending], user: &str) -> usize {
+        let reserved = pending
+            .iter()
+            .filter(|entry| entry.user.as_deref() == Some(user));
+        self.count(|entry| entry.user.as_deref() == Some(user)) + reserved.count()
+    }
+
+    fn peer_streams(&self, pending: &[Pending], peer: IpAddr) -> usize {
+        let reserved = pending.iter().filter(|entry| entry.peer == peer);
+        self.count(|entry| entry.peer == peer) + reserved.count()
+    }
+
+    fn count(&self, matches: impl Fn(&ClientEntry<W>) -> bool) -> usize {
+        let mut count = 0;
+        self.registry.for_all(|_, entry| {
+            if matches(entry) {
+                count += 1;
+            }
+        });
+        count
+    }
+
+    fn is_full(&self, pending: &[Pending]) -> bool {
+        match self.config.max_clients {
+            Some(max) => self.registry.len() + pending.len() >= max,
+            None => false,
+        }
+    }
+
+    pub fn prune(&self) {
+        let mut closed = Vec::new();
+        self.registry.for_all(|id, entry| {
+            if entry.queue.is_closed() {
+                closed.push(id);
+            }
+        });
+        self.remove(closed);
+    }
+
+    pub fn dispatch(&self, event: &MarketEvent) {
+        let mut closed = Vec::new();
+        let mut deliver = |id, entry: &ClientEntry<W>| {
+            if entry.options.depth || !event.is_depth() {
+                match entry.queue.push(event.clone()) {
+                    Delivery::Queued => entry.waker.wake(),
+                    Delivery::Dropped => {}
+                    Delivery::Disconnect => closed.push(id),
+                }
+            }
+        };
+        match event.ticker() {
+            Some(ticker) => self.registry.for_ticker(ticker, &mut deliver),
+            None => self.registry.for_all(&mut deliver),
+        }
+        self.remove(closed);
+    }
+
+    fn remove(&self, ids: Vec<SubscriberId>) {
+        for id in ids {
+            if let Some(entry) = self.registry.remove(id) {
+                info!(
+                    "stream {} removed {}, {} events dropped",
+                    entry.id,
+                    entry.addr,
+                    entry.queue.dropped()
+                );
+            }
+        }
+    }
+}
+
+impl<W> Drop for Admission<'_, W> {
+    fn drop(&mut self) {
+        if let Ok(mut pending) = self.hub.pending.lock() {
+            pending.retain(|entry| entry.id != self.ticket.id);
+        }
+    }
+}
+
+impl<W: Wake + Send + Sync> StreamDirectory for Hub<W> {
+    fn streams(&self) -> Vec<StreamInfo> {
+        let mut streams = Vec::new();
+        self.registry.for_all(|_, entry| {
+            if !entry.queue.is_closed() {
+                streams.push(StreamInfo {
+                    id: entry.id,
+                    user: entry.user.clone(),
+                    addr: entry.addr,
+                    tickers: entry.tickers.clone(),
+                    options: entry.options.clone(),
+                    policy: entry.queue.policy(),
+                    dropped: entry.queue.dropped(),
+                });
+            }
+        });
+        streams.sort_by_key(|stream| stream.id);
+        streams
+    }
+
+    fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+}
@@ -0,0 +1,19 @@
+#[cfg(feature = "async")]
+pub mod async_server;
+pub mod auth;
+pub mod config;
+pub mod fanout;
+mod handle;
+mod handshake;
+mod hub;
+pub mod metrics;
+pub mod queue;
+pub mod registry;
+pub mod server;
+pub mod shutdown;
+mod stream;
+pub mod tls;
+
+pub use config::ServerConfig;
+pub use handle::{QuoteServer, QuoteServerBuilder, StreamInfo};
+pub use shutdown::Shutdown;
@@ -1,13 +1,290 @@
-mod server;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::server::run_server;
-use log::error;
+use clap::Parser;
+use ipnet::IpNet;
+use log::{error, info};
+use quote_core::generator::DEFAULT_TICKERS;
+use quote_core::{
+    AuthRequest, OverflowPolicy, QuoteGenerator, QuoteSource, ReplaySource, ReplaySpeed, Scenario,
+    SessionSchedule, SourceError, UpdateRate, UpstreamConfig, UpstreamSource, now_millis,
+    parse_duration,
+};
+use quote_server::auth::Credentials;
+use quote_server::config::{
+    DEFAULT_BIND_ADDR, DEFAULT_CLIENT_QUEUE, DEFAULT_HEARTBEAT_INTERVAL, DEFAULT_KEEPALIVE_TIMEOUT,
+    DEFAULT_UDP_WORKERS, FileConfig, LogFormat, ServerConfig, load_universe,
+};
+use quote_server::server::run_server;
+use quote_server::shutdown::Shutdown;
+use quote_server::tls;
 
-const DEFAULT_SERVER_ADDR: &str = "127.0.0.1:7878";
+const DEFAULT_BOOK_DEPTH: usize = 5;
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[arg(long = "config")]
+    config: Option<PathBuf>,
+    #[arg(long = "bind")]
+    bind: Option<String>,
+    #[arg(long = "tick-interval", value_parser = parse_interval)]
+    tick_interval: Option<Duration>,
+    #[arg(long = "keepalive-timeout", value_parser = parse_interval)]
+    keepalive_timeout: Option<Duration>,
+    #[arg(long = "heartbeat-interval", value_parser = parse_interval)]
+    heartbeat_interval: Option<Duration>,
+    #[arg(long = "max-clients")]
+    max_clients: Option<usize>,
+    #[arg(long = "max-streams-per-ip")]
+    max_streams_per_ip: Option<usize>,
+    #[arg(long = "max-tickers")]
+    max_tickers: Option<usize>,
+    #[arg(long = "max-rate")]
+    max_rate: Option<u32>,
+    #[arg(long = "universe")]
+    universe: Option<PathBuf>,
+    #[arg(long = "credentials")]
+    credentials: Option<PathBuf>,
+    #[arg(long = "udp-allow", value_delimiter = ',')]
+    udp_allow: Vec<IpNet>,
+    #[arg(long = "no-udp-proof")]
+    no_udp_proof: bool,
+    #[arg(long = "tls-cert")]
+    tls_cert: Option<PathBuf>,
+    #[arg(long = "tls-key")]
+    tls_key: Option<PathBuf>,
+    #[arg(long = "tls-client-ca")]
+    tls_client_ca: Option<PathBuf>,
+    #[arg(long = "log-format")]
+    log_format: Option<LogFormat>,
+    #[arg(long = "replay", conflicts_with = "upstream")]
+    replay: Vec<PathBuf>,
+    #[arg(long = "replay-speed", default_value = "original")]
+    replay_speed: ReplaySpeed,
+    #[arg(long = "upstream")]
+    upstream: Option<String>,
+    #[arg(long = "upstream-user", requires_all = ["upstream", "upstream_token"])]
+    upstream_user: Option<String>,
+    #[arg(long = "upstream-token", requires = "upstream_user")]
+    upstream_token: Option<String>,
+    #[arg(long = "upstream-tls-ca", requires = "upstream")]
+    upstream_tls_ca: Option<PathBuf>,
+    #[arg(long = "upstream-tls-cert", requires_all = ["upstream_tls_ca", "upstream_tls_key"])]
+    upstream_tls_cert: Option<PathBuf>,
+    #[arg(long = "upstream-tls-key", requires = "upstream_tls_cert")]
+    upstream_tls_key: Option<PathBuf>,
+    #[arg(long = "scenario", conflicts_with_all = ["replay", "upstream"])]
+    scenario: Option<PathBuf>,
+    #[arg(long = "market-hours", conflicts_with_all = ["replay", "upstream"])]
+    market_hours: bool,
+    #[arg(long = "book-depth")]
+    book_depth: Option<usize>,
+    #[arg(long = "client-queue")]
+    client_queue: Option<usize>,
+    #[arg(long = "overflow")]
+    overflow: Option<OverflowPolicy>,
+    #[arg(long = "udp-workers")]
+    udp_workers: Option<usize>,
+    #[cfg(feature = "async")]
+    #[arg(long = "async")]
+    async_runtime: bool,
+}
+
+struct Settings {
+    bind: String,
+    tick_interval: Option<Duration>,
+    universe: Vec<String>,
+    book_depth: usize,
+    config: ServerConfig,
+}
 
 fn main() {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
-    if let Err(err) = run_server(DEFAULT_SERVER_ADDR) {
+    let args = Args::parse();
+    let file = match &args.config {
+        Some(path) => FileConfig::from_file(path).map_err(|e| format!("config error: {e}")),
+        None => Ok(FileConfig::default()),
+    };
+    let log_format = args
+        .log_format
+        .or(file.as_ref().ok().and_then(|f| f.log_format))
+        .unwrap_or_default();
+    init_logging(log_format);
+    let settings = match file.and_then(|file| settings(&args, file)) {
+        Ok(settings) => settings,
+        Err(err) => {
+            error!("{err}");
+            return;
+        }
+    };
+    let source = match build_source(&args, &settings) {
+        Ok(source) => source,
+        Err(err) => {
+            error!("{err}");
+            return;
+        }
+    };
+    let shutdown = Shutdown::new();
+    let _ = ctrlc::set_handler({
+        let shutdown = shutdown.clone();
+        move || shutdown.trigger()
+    });
+    info!(
+        "universe of {} tickers, keep-alive {:?}",
+        settings.universe.len(),
+        settings.config.keepalive_timeout
+    );
+    let Settings { bind, config, .. } = settings;
+    #[cfg(feature = "async")]
+    let result = if args.async_runtime {
+        quote_server::async_server::run_server_async(&bind, config, source, &shutdown)
+    } else {
+        run_server(&bind, config, source, &shutdown)
+    };
+    #[cfg(not(feature = "async"))]
+    let result = run_server(&bind, config, source, &shutdown);
+    if let Err(err) = result {
         error!("server error: {err}");
     }
 }
+
+fn settings(args: &Args, file: FileConfig) -> Result<Settings, String> {
+    let universe = match args.universe.as_ref().or(file.universe.as_ref()) {
+        Some(path) => load_universe(path).map_err(|e| format!("universe error: {e}"))?,
+        None => DEFAULT_TICKERS.iter().map(|t| t.to_string()).collect(),
+    };
+    let credentials = match args.credentials.as_ref().or(file.credentials.as_ref()) {
+        Some(path) => Some(Arc::new(
+            Credentials::from_file(path).map_err(|e| format!("credentials error: {e}"))?,
+        )),
+        None => None,
+    };
+    let tls = match (
+        args.tls_cert.as_ref().or(file.tls_cert.as_ref()),
+        args.tls_key.as_ref().or(file.tls_key.as_ref()),
+        args.tls_client_ca.as_ref().or(file.tls_client_ca.as_ref()),
+    ) {
+        (Some(cert), Some(key), client_ca) => Some(
+            tls::server_config(cert, key, client_ca.map(PathBuf::as_path))
+                .map_err(|e| format!("tls error: {e}"))?,
+        ),
+        (None, None, None) => None,
+        _ => return Err("tls error: both --tls-cert and --tls-key are required".to_string()),
+    };
+    let book_depth = args
+        .book_depth
+        .or(file.book_depth)
+        .unwrap_or(DEFAULT_BOOK_DEPTH);
+    let config = ServerConfig {
+        client_queue: args
+            .client_queue
+            .or(file.client_queue)
+            .unwrap_or(DEFAULT_CLIENT_QUEUE),
+        overflow: args.overflow.or(file.overflow).unwrap_or_default(),
+        udp_workers: args
+            .udp_workers
+            .or(file.udp_workers)
+            .unwrap_or(DEFAULT_UDP_WORKERS),
+        keepalive_timeout: args
+            .keepalive_timeout
+            .or(file.keepalive_timeout)
+            .unwrap_or(DEFAULT_KEEPALIVE_TIMEOUT),
+        heartbeat_interval: args
+            .heartbeat_interval
+            .or(file.heartbeat_interval)
+            .unwrap_or(DEFAULT_HEARTBEAT_INTERVAL),
+        max_clients: args.max_clients.or(file.max_clients),
+        max_streams_per_ip: args.max_streams_per_ip.or(file.max_streams_per_ip),
+        max_tickers: args.max_tickers.or(file.max_tickers),
+        max_rate: args.max_rate.or(file.max_rate),
+        depth: book_depth > 0 && args.upstream.is_none() && args.replay.is_empty(),
+        credentials,
+        udp_allow: match &args.udp_allow {
+            allow if allow.is_empty() => file.udp_allow.unwrap_or_default(),
+            allow => allow.clone(),
+        },
+        udp_proof: !args.no_udp_proof && file.udp_proof.unwrap_or(true),
+        tls,
+        ..ServerConfig::default()
+    };
+    Ok(Settings {
+        bind: args
+            .bind
+            .clone()
+            .or(file.bind)
+            .unwrap_or_else(|| DEFAULT_BIND_ADDR.to_string()),
+        tick_interval: args.tick_interval.or(file.tick_interval),
+        universe,
+        book_depth,
+        config,
+    })
+}
+
+fn init_logging(format: LogFormat) {
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = serde_json::json!({
+                "ts": now_millis(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "msg": record.args().to_string(),
+            });
+            writeln!(buf, "{line}")
+        });
+    }
+    builder.init();
+}
+
+fn build_source(args: &Args, settings: &Settings) -> Result<Box<dyn QuoteSource>, String> {
+    if let Some(upstream) = &args.upstream {
+        let mut config = UpstreamConfig::new(upstream, &settings.universe);
+        config.auth = args
+            .upstream_user
+            .clone()
+            .zip(args.upstream_token.clone())
+            .map(|(user, token)| AuthRequest { user, token });
+        if let Some(ca) = &args.upstream_tls_ca {
+            let identity = args
+                .upstream_tls_cert
+                .as_deref()
+                .zip(args.upstream_tls_key.as_deref());
+            config.tls = Some(
+                quote_core::tls::client_config(ca, identity)
+                    .map_err(|e| format!("tls error: {e}"))?,
+            );
+        }
+        let source = UpstreamSource::connect(config).map_err(source_error)?;
+        return Ok(Box::new(source));
+    }
+    if !args.replay.is_empty() {
+        let source = ReplaySource::from_files(&args.replay, args.replay_speed)
+            .map_err(|e| source_error(e.into()))?;
+        return Ok(Box::new(source));
+    }
+    let mut generator = QuoteGenerator::new(settings.universe.clone());
+    if let Some(interval) = settings.tick_interval {
+        for ticker in &settings.universe {
+            generator.set_rate(ticker, UpdateRate::Fixed(interval));
+        }
+    }
+    generator.set_depth(settings.book_depth);
+    if let Some(path) = &args.scenario {
+        let scenario = Scenario::from_file(path).map_err(|e| format!("scenario error: {e}"))?;
+        generator.set_scenario(scenario);
+    }
+    if args.market_hours {
+        generator.set_sessions(SessionSchedule::us_equities());
+    }
+    Ok(Box::new(generator))
+}
+
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    parse_duration(value).ok_or_else(|| format!("invalid interval: {value}"))
+}
+
+fn source_error(err: SourceError) -> String {
+    format!("source error: {err}")
+}
@@ -0,0 +1,39 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::auth::Rejection;
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub dropped_events: AtomicU64,
+    pub slow_disconnects: AtomicU64,
+    pub rejected_streams: AtomicU64,
+    pub rejected_bad_request: AtomicU64,
+    pub rejected_client_limit: AtomicU64,
+    pub rejected_ip_limit: AtomicU64,
+    pub rejected_ticker_limit: AtomicU64,
+    pub rejected_target_not_allowed: AtomicU64,
+    pub rejected_auth_required: AtomicU64,
+    pub rejected_auth_failed: AtomicU64,
+    pub rejected_not_entitled: AtomicU64,
+    pub rejected_stream_limit: AtomicU64,
+    pub rejected_depth_unavailable: AtomicU64,
+}
+
+impl Metrics {
+    pub fn record_rejection(&self, rejection: &Rejection) {
+        self.rejected_streams.fetch_add(1, Ordering::Relaxed);
+        let counter = match rejection {
+            Rejection::BadRequest(_) => &self.rejected_bad_request,
+            Rejection::TooManyClients => &self.rejected_client_limit,
+            Rejection::IpLimit(_) => &self.rejected_ip_limit,
+            Rejection::TooManyTickers(_) => &self.rejected_ticker_limit,
+            Rejection::TargetNotAllowed => &self.rejected_target_not_allowed,
+            Rejection::AuthRequired => &self.rejected_auth_required,
+            Rejection::AuthFailed => &self.rejected_auth_failed,
+            Rejection::NotEntitled(_) => &self.rejected_not_entitled,
+            Rejection::StreamLimit(_) => &self.rejected_stream_limit,
+            Rejection::DepthUnavailable => &self.rejected_depth_unavailable,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
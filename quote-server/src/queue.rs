@@ -0,0 +1,228 @@
+use crossbeam_channel::{Receiver, Sender, TryRecvError, TrySendError, bounded};
+use log::{debug, warn};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use quote_core::{Conflator, MarketEvent, OverflowPolicy};
+
+use crate::metrics::Metrics;
+
+const DROP_LOG_EVERY: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Queued,
+    Dropped,
+    Disconnect,
+}
+
+pub struct ClientQueue {
+    addr: SocketAddr,
+    policy: OverflowPolicy,
+    tx: Sender<MarketEvent>,
+    rx: Receiver<MarketEvent>,
+    overflow: Arc<Mutex<Conflator>>,
+    dropped: AtomicU64,
+    metrics: Arc<Metrics>,
+    closed: Arc<AtomicBool>,
+}
+
+pub struct QueueReceiver {
+    rx: Receiver<MarketEvent>,
+    overflow: Arc<Mutex<Conflator>>,
+    closed: Arc<AtomicBool>,
+}
+
+pub fn client_queue(
+    addr: SocketAddr,
+    capacity: usize,
+    policy: OverflowPolicy,
+    metrics: Arc<Metrics>,
+) -> (ClientQueue, QueueReceiver) {
+    let (tx, rx) = bounded(capacity.max(1));
+    let overflow = Arc::new(Mutex::new(Conflator::new()));
+    let closed = Arc::new(AtomicBool::new(false));
+    let queue = ClientQueue {
+        addr,
+        policy,
+        tx,
+        rx: rx.clone(),
+        overflow: overflow.clone(),
+        dropped: AtomicU64::new(0),
+        metrics,
+        closed: closed.clone(),
+    };
+    (
+        queue,
+        QueueReceiver {
+            rx,
+            overflow,
+            closed,
+        },
+    )
+}
+
+impl ClientQueue {
+    pub fn push(&self, event: MarketEvent) -> Delivery {
+        if self.is_closed() {
+            return Delivery::Disconnect;
+        }
+        let event = match self.tx.try_send(event) {
+            Ok(()) => return Delivery::Queued,
+            Err(TrySendError::Disconnected(_)) => return Delivery::Disconnect,
+            Err(TrySendError::Full(event)) => event,
+        };
+        match (self.policy, event) {
+            (OverflowPolicy::Disconnect, _) => {
+                warn!("slow consumer {} disconnected", self.addr);
+                self.metrics
+                    .slow_disconnects
+                    .fetch_add(1, Ordering::Relaxed);
+                Delivery::Disconnect
+            }
+            (OverflowPolicy::DropNewest, _) => {
+                self.record_drop();
+                Delivery::Dropped
+            }
+            (OverflowPolicy::Conflate, MarketEvent::Quote(quote)) => {
+                if let Ok(mut overflow) = self.overflow.lock() {
+                    overflow.push(quote);
+                }
+                Delivery::Queued
+            }
+            (OverflowPolicy::DropOldest | OverflowPolicy::Conflate, event) => {
+                if self.rx.try_recv().is_ok() {
+                    self.record_drop();
+                }
+                match self.tx.try_send(event) {
+                    Ok(()) => Delivery::Queued,
+                    Err(TrySendError::Disconnected(_)) => Delivery::Disconnect,
+                    Err(TrySendError::Full(_)) => {
+                        self.record_drop();
+                        Delivery::Dropped
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn record_drop(&self) {
+        let count = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        self.metrics.dropped_events.fetch_add(1, Ordering::Relaxed);
+        debug!("dropped event for {} ({})", self.addr, self.policy);
+        if count == 1 || count.is_multiple_of(DROP_LOG_EVERY) {
+            warn!(
+                "slow consumer {}: {count} events dropped ({})",
+                self.addr, self.policy
+            );
+        }
+    }
+}
+
+impl QueueReceiver {
+    pub fn try_recv(&self) -> Result<MarketEvent, TryRecvError> {
+        self.rx.try_recv()
+    }
+
+    pub fn try_iter(&self) -> impl Iterator<Item = MarketEvent> + '_ {
+        self.rx.try_iter()
+    }
+
+    pub fn take_overflow(&self) -> Vec<MarketEvent> {
+        match self.overflow.lock() {
+            Ok(mut overflow) if !overflow.is_empty() => overflow
+                .drain()
+                .into_iter()
+                .map(MarketEvent::Quote)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Drop for QueueReceiver {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quote_core::StockQuote;
+
+    use super::*;
+
+    fn quote(price: f64) -> MarketEvent {
+        MarketEvent::Quote(StockQuote {
+            ticker: "AAPL".to_string(),
+            price,
+            volume: 1,
+            timestamp: 0,
+            seq: None,
+        })
+    }
+
+    fn queue(policy: OverflowPolicy) -> (ClientQueue, QueueReceiver, Arc<Metrics>) {
+        let metrics = Arc::new(Metrics::default());
+        let addr = "127.0.0.1:1".parse().unwrap();
+        let (queue, rx) = client_queue(addr, 2, policy, metrics.clone());
+        (queue, rx, metrics)
+    }
+
+    fn prices(rx: &QueueReceiver) -> Vec<f64> {
+        rx.try_iter()
+            .chain(rx.take_overflow())
+            .filter_map(|e| match e {
+                MarketEvent::Quote(q) => Some(q.price),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn applies_overflow_policies() {
+        let (q, rx, metrics) = queue(OverflowPolicy::DropOldest);
+        for price in 1..=4 {
+            q.push(quote(price as f64));
+        }
+        assert_eq!(prices(&rx), vec![3.0, 4.0]);
+        assert_eq!(q.dropped(), 2);
+        assert_eq!(metrics.dropped_events.load(Ordering::Relaxed), 2);
+
+        let (q, rx, _) = queue(OverflowPolicy::DropNewest);
+        for price in 1..=4 {
+            q.push(quote(price as f64));
+        }
+        assert_eq!(prices(&rx), vec![1.0, 2.0]);
+
+        let (q, rx, _) = queue(OverflowPolicy::Conflate);
+        for price in 1..=4 {
+            q.push(quote(price as f64));
+        }
+        assert_eq!(prices(&rx), vec![1.0, 2.0, 4.0]);
+
+        let (q, _rx, metrics) = queue(OverflowPolicy::Disconnect);
+        assert_eq!(q.push(quote(1.0)), Delivery::Queued);
+        assert_eq!(q.push(quote(2.0)), Delivery::Queued);
+        assert_eq!(q.push(quote(3.0)), Delivery::Disconnect);
+        assert_eq!(metrics.slow_disconnects.load(Ordering::Relaxed), 1);
+
+        let (q, rx, _) = queue(OverflowPolicy::DropOldest);
+        drop(rx);
+        assert!(q.is_closed());
+        assert_eq!(q.push(quote(1.0)), Delivery::Disconnect);
+    }
+}
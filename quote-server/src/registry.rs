@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+pub type SubscriberId = u64;
+
+pub struct Registry<T> {
+    index: RwLock<Index<T>>,
+    next_id: AtomicU64,
+}
+
+struct Index<T> {
+    by_ticker: HashMap<String, Vec<(SubscriberId, Arc<T>)>>,
+    subscribers: HashMap<SubscriberId, (Vec<String>, Arc<T>)>,
+}
+
+impl<T> Registry<T> {
+    pub fn new() -> Self {
+        Registry {
+            index: RwLock::new(Index {
+                by_ticker: HashMap::new(),
+                subscribers: HashMap::new(),
+            }),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn insert(&self, tickers: &[String], value: T) -> SubscriberId {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let value = Arc::new(value);
+        let mut tickers = tickers.iter().map(|t| t.to_uppercase()).collect::<Vec<_>>();
+        tickers.sort();
+        tickers.dedup();
+        if let Ok(mut index) = self.index.write() {
+            for ticker in &tickers {
+                index
+                    .by_ticker
+                    .entry(ticker.clone())
+                    .or_default()
+                    .push((id, value.clone()));
+            }
+            index.subscribers.insert(id, (tickers, value));
+        }
+        id
+    }
+
+    pub fn remove(&self, id: SubscriberId) -> Option<Arc<T>> {
+        let mut index = self.index.write().ok()?;
+        let (tickers, value) = index.subscribers.remove(&id)?;
+        for ticker in tickers {
+            if let Some(list) = index.by_ticker.get_mut(&ticker) {
+                list.retain(|(other, _)| *other != id);
+                if list.is_empty() {
+                    index.by_ticker.remove(&ticker);
+                }
+            }
+        }
+        Some(value)
+    }
+
+    pub fn for_ticker(&self, ticker: &str, mut visit: impl FnMut(SubscriberId, &T)) {
+        if let Ok(index) = self.index.read()
+            && let Some(list) = index.by_ticker.get(ticker)
+        {
+            for (id, value) in list {
+                visit(*id, value);
+            }
+        }
+    }
+
+    pub fn for_all(&self, mut visit: impl FnMut(SubscriberId, &T)) {
+        if let Ok(index) = self.index.read() {
+            for (id, (_, value)) in &index.subscribers {
+                visit(*id, value);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.read().map(|i| i.subscribers.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Default for Registry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tickers(list: &[&str]) -> Vec<String> {
+        list.iter().map(|t| t.to_string()).collect()
+    }
+
+    fn visited(registry: &Registry<&'static str>, ticker: &str) -> Vec<&'static str> {
+        let mut out = Vec::new();
+        registry.for_ticker(ticker, |_, name| out.push(*name));
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn indexes_subscribers_by_ticker() {
+        let registry = Registry::new();
+        let a = registry.insert(&tickers(&["aapl", "MSFT"]), "a");
+        registry.insert(&tickers(&["AAPL", "AAPL"]), "b");
+        assert_eq!(visited(&registry, "AAPL"), vec!["a", "b"]);
+        assert_eq!(visited(&registry, "MSFT"), vec!["a"]);
+        assert!(visited(&registry, "TSLA").is_empty());
+
+        assert_eq!(registry.remove(a).as_deref(), Some(&"a"));
+        assert!(registry.remove(a).is_none());
+        assert_eq!(visited(&registry, "AAPL"), vec!["b"]);
+        assert!(visited(&registry, "MSFT").is_empty());
+        assert_eq!(registry.len(), 1);
+    }
+}
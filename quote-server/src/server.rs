@@ -1,165 +1,180 @@
-use std::collections::HashSet;
-use std::io::{BufRead, BufReader, Write};
-use std::net::{TcpListener, TcpStream, UdpSocket};
-use std::sync::{Arc, Mutex};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
-use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, unbounded};
-use log::{error, info, warn};
+use crossbeam_channel::{Receiver, Sender, bounded};
+use log::{error, info};
 
-use quote_core::{QuoteGenerator, StockQuote, StreamRequest, parse_command};
+use quote_core::{MarketEvent, QuoteSource, Sequencer, StreamAccepted};
 
-const GENERATE_INTERVAL: Duration = Duration::from_millis(200);
-const STREAM_TIMEOUT: Duration = Duration::from_secs(5);
-const DISPATCH_TIMEOUT: Duration = Duration::from_millis(200);
-const UDP_BIND_ADDR: &str = "0.0.0.0:0";
-const PING_WORD: &str = "ping";
-const PING_REPLY: &[u8] = b"Pong";
+use crate::config::ServerConfig;
+use crate::fanout::{FanoutPool, Waker};
+use crate::handshake::{Handshake, Step};
+use crate::hub::{Hub, StreamDirectory};
+use crate::shutdown::Shutdown;
+use crate::tls::Control;
 
-struct ClientEntry {
-    filter: HashSet<String>,
-    tx: Sender<StockQuote>,
+const ACCEPT_POLL: Duration = Duration::from_millis(50);
+pub(crate) const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Shared {
+    hub: Arc<Hub<Waker>>,
+    fanout: FanoutPool,
 }
 
-pub fn run_server(addr: &str) -> std::io::Result<()> {
+pub fn run_server(
+    addr: &str,
+    config: ServerConfig,
+    source: Box<dyn QuoteSource>,
+    shutdown: &Shutdown,
+) -> std::io::Result<()> {
     let listener = TcpListener::bind(addr)?;
-    let (quote_tx, quote_rx) = unbounded();
-    let registry: Arc<Mutex<Vec<ClientEntry>>> = Arc::new(Mutex::new(Vec::new()));
-    let _gen = spawn_generator(quote_tx);
-    let _dispatcher = spawn_dispatcher(quote_rx, registry.clone());
-    info!("listening on {addr}");
-    for stream in listener.incoming() {
-        match stream {
-            Ok(stream) => {
-                let registry_clone = registry.clone();
-                thread::spawn(move || {
-                    if let Err(err) = handle_connection(stream, registry_clone) {
+    serve(listener, Arc::new(Hub::new(config)), source, shutdown)
+}
+
+pub(crate) fn spawn(
+    listener: TcpListener,
+    config: ServerConfig,
+    source: Box<dyn QuoteSource>,
+    shutdown: Shutdown,
+) -> (
+    Arc<dyn StreamDirectory>,
+    thread::JoinHandle<std::io::Result<()>>,
+) {
+    let hub = Arc::new(Hub::new(config));
+    let directory = hub.clone();
+    let handle = thread::spawn(move || serve(listener, hub, source, &shutdown));
+    (directory, handle)
+}
+
+fn serve(
+    listener: TcpListener,
+    hub: Arc<Hub<Waker>>,
+    source: Box<dyn QuoteSource>,
+    shutdown: &Shutdown,
+) -> std::io::Result<()> {
+    listener.set_nonblocking(true)?;
+    let config = &hub.config;
+    let (quote_tx, quote_rx) = bounded(config.source_queue.max(1));
+    let fanout = FanoutPool::spawn(
+        config.udp_workers,
+        config.keepalive_timeout,
+        config.heartbeat_interval,
+        config.max_rate,
+    )?;
+    let shared = Arc::new(Shared { hub, fanout });
+    let generator = spawn_generator(quote_tx, source, shutdown.clone());
+    let dispatcher = spawn_dispatcher(quote_rx, shared.clone());
+    let mut connections: Vec<thread::JoinHandle<()>> = Vec::new();
+    info!("listening on {}", listener.local_addr()?);
+    while !shutdown.is_triggered() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let shared = shared.clone();
+                connections.retain(|handle| !handle.is_finished());
+                connections.push(thread::spawn(move || {
+                    if let Err(err) = handle_connection(stream, shared) {
                         error!("client error: {err}");
                     }
-                });
+                }));
             }
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL),
             Err(err) => error!("accept error: {err}"),
         }
     }
+    info!("shutting down");
+    drop(listener);
+    for handle in connections {
+        let _ = handle.join();
+    }
+    let _ = generator.join();
+    let _ = dispatcher.join();
+    shared.fanout.shutdown();
+    info!("server stopped");
     Ok(())
 }
 
-fn spawn_generator(tx: Sender<StockQuote>) -> thread::JoinHandle<()> {
+fn spawn_generator(
+    tx: Sender<MarketEvent>,
+    mut source: Box<dyn QuoteSource>,
+    shutdown: Shutdown,
+) -> thread::JoinHandle<()> {
     thread::spawn(move || {
-        let mut generator = QuoteGenerator::default();
-        loop {
-            let batch = generator.generate_all();
-            for quote in batch {
-                let _ = tx.send(quote);
+        let mut sequencer = Sequencer::default();
+        while !shutdown.is_triggered()
+            && let Some(batch) = source.next_batch()
+        {
+            match batch {
+                Ok(events) => {
+                    for mut event in events {
+                        sequencer.stamp(&mut event);
+                        let _ = tx.send(event);
+                    }
+                }
+                Err(err) => {
+                    error!("source error: {err}");
+                    break;
+                }
             }
-            thread::sleep(GENERATE_INTERVAL);
         }
+        info!("source finished");
     })
 }
 
-fn spawn_dispatcher(
-    rx: Receiver<StockQuote>,
-    registry: Arc<Mutex<Vec<ClientEntry>>>,
-) -> thread::JoinHandle<()> {
+fn spawn_dispatcher(rx: Receiver<MarketEvent>, shared: Arc<Shared>) -> thread::JoinHandle<()> {
     thread::spawn(move || {
-        while let Ok(quote) = rx.recv() {
-            if let Ok(mut guard) = registry.lock() {
-                let mut index = 0;
-                while index < guard.len() {
-                    let needs = guard[index].filter.contains(&quote.ticker);
-                    let keep = if needs {
-                        guard[index].tx.send(quote.clone()).is_ok()
-                    } else {
-                        true
-                    };
-                    if keep {
-                        index += 1;
-                    } else {
-                        guard.remove(index);
-                    }
-                }
-            }
+        while let Ok(event) = rx.recv() {
+            shared.hub.dispatch(&event);
         }
     })
 }
 
-fn handle_connection(
-    stream: TcpStream,
-    registry: Arc<Mutex<Vec<ClientEntry>>>,
-) -> std::io::Result<()> {
-    if let Ok(addr) = stream.peer_addr() {
-        info!("tcp connect {addr}");
-    }
-    let mut reader = BufReader::new(stream.try_clone()?);
+fn handle_connection(stream: TcpStream, shared: Arc<Shared>) -> std::io::Result<()> {
+    let peer = stream.peer_addr()?.ip();
+    let local = stream.local_addr()?;
+    info!("tcp connect {peer}");
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(COMMAND_TIMEOUT))?;
+    let mut reader = BufReader::new(Control::accept(stream, shared.hub.config.tls.as_ref())?);
+    let mut handshake = Handshake::new(&shared.hub, peer);
     let mut buffer = String::new();
-    reader.read_line(&mut buffer)?;
-    if buffer.trim().is_empty() {
-        return Ok(());
-    }
-    match parse_command(&buffer) {
-        Ok(request) => {
-            let (tx, rx) = unbounded();
-            let filter = request
-                .tickers
-                .iter()
-                .map(|t| t.to_uppercase())
-                .collect::<HashSet<_>>();
-            if let Ok(mut guard) = registry.lock() {
-                guard.push(ClientEntry { filter, tx });
-            }
-            let mut writer = stream;
-            let _ = writer.write_all(b"OK\n");
-            let _ = writer.flush();
-            info!("stream start {}", request.addr);
-            thread::spawn(move || stream_quotes(request, rx));
+    let (admission, request) = loop {
+        buffer.clear();
+        reader.read_line(&mut buffer)?;
+        match handshake.on_line(&buffer) {
+            Step::Reply(line) => send(reader.get_mut(), &line)?,
+            Step::Reject(line) => return send(reader.get_mut(), &line),
+            Step::Accept(admission, request) => break (admission, request),
+            Step::Close => return Ok(()),
         }
-        Err(err) => {
-            warn!("command parse error: {err}");
-            let mut writer = stream;
-            let message = format!("ERR {err}\n");
-            let _ = writer.write_all(message.as_bytes());
-            let _ = writer.flush();
-        }
-    }
+    };
+    let worker = shared.fanout.assign();
+    let accepted = StreamAccepted {
+        ticket: admission.ticket.clone(),
+        endpoint: SocketAddr::new(local.ip(), shared.fanout.udp_port(worker)),
+    };
+    let mut writer = reader.into_inner();
+    writer.write_all(format!("{accepted}\n").as_bytes())?;
+    writer.flush()?;
+    writer.socket().set_read_timeout(None)?;
+    let ticket = accepted.ticket;
+    let challenge = shared.hub.challenge();
+    shared.hub.subscribe(admission, &request, |rx| {
+        shared.fanout.attach(
+            worker,
+            &request,
+            ticket.clone(),
+            challenge,
+            rx,
+            Some(writer),
+        )
+    });
     Ok(())
 }
 
-fn stream_quotes(request: StreamRequest, rx: Receiver<StockQuote>) {
-    let socket = match UdpSocket::bind(UDP_BIND_ADDR) {
-        Ok(s) => s,
-        Err(err) => {
-            error!("udp bind error: {err}");
-            return;
-        }
-    };
-    let _ = socket.set_nonblocking(true);
-    let mut last_ping = Instant::now();
-    let mut buf = [0u8; 256];
-    loop {
-        if last_ping.elapsed() > STREAM_TIMEOUT {
-            break;
-        }
-        match socket.recv_from(&mut buf) {
-            Ok((n, src)) => {
-                if let Ok(msg) = std::str::from_utf8(&buf[..n]) {
-                    if msg.trim().eq_ignore_ascii_case(PING_WORD) {
-                        last_ping = Instant::now();
-                        let _ = socket.send_to(PING_REPLY, src);
-                    }
-                }
-            }
-            Err(ref err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
-            Err(_) => break,
-        }
-        match rx.recv_timeout(DISPATCH_TIMEOUT) {
-            Ok(quote) => {
-                let payload = quote.to_string();
-                let _ = socket.send_to(payload.as_bytes(), request.addr);
-            }
-            Err(RecvTimeoutError::Timeout) => {}
-            Err(_) => break,
-        }
-    }
-    info!("stream stop {}", request.addr);
+fn send(writer: &mut Control, line: &str) -> std::io::Result<()> {
+    writer.write_all(line.as_bytes())?;
+    writer.flush()
 }
@@ -0,0 +1,388 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::TryRecvError;
+use quote_core::{
+    BarAggregator, Conflator, ControlEvent, MarketEvent, StreamOptions, StreamTicket, parse_ping,
+    parse_proof,
+};
+
+use crate::queue::QueueReceiver;
+
+pub const DISPATCH_TIMEOUT: Duration = Duration::from_millis(200);
+const BAR_PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+const CHALLENGE_INTERVAL: Duration = Duration::from_secs(1);
+const DRAIN_BUDGET: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Drain {
+    Idle,
+    Pending,
+    Closed,
+}
+
+pub struct StreamState {
+    bars: Option<BarAggregator>,
+    last_bars: Instant,
+    last_quote: Option<(u64, Instant)>,
+    conflator: Option<Conflator>,
+    conflate_every: Duration,
+    last_flush: Instant,
+    heartbeat: Duration,
+    last_sent: Instant,
+    last_seqs: BTreeMap<String, u64>,
+    challenge: Option<String>,
+    last_challenge: Option<Instant>,
+    rate: Option<u32>,
+    allowance: f64,
+    last_refill: Instant,
+    throttled: bool,
+}
+
+impl StreamState {
+    pub fn new(options: &StreamOptions, heartbeat: Duration) -> Self {
+        StreamState {
+            bars: options.bars.map(BarAggregator::new),
+            last_bars: Instant::now(),
+            last_quote: None,
+            conflator: options.conflate.map(|_| Conflator::new()),
+            conflate_every: options.conflate.unwrap_or_default(),
+            last_flush: Instant::now(),
+            heartbeat,
+            last_sent: Instant::now(),
+            last_seqs: BTreeMap::new(),
+            challenge: None,
+            last_challenge: None,
+            rate: None,
+            allowance: 0.0,
+            last_refill: Instant::now(),
+            throttled: false,
+        }
+    }
+
+    pub fn limit_rate(&mut self, per_second: u32) {
+        self.rate = Some(per_second.max(1));
+        self.allowance = f64::from(per_second.max(1));
+        self.last_refill = Instant::now();
+    }
+
+    pub fn require_proof(&mut self, nonce: String) {
+        self.challenge = Some(nonce);
+    }
+
+    pub fn is_verified(&self) -> bool {
+        self.challenge.is_none()
+    }
+
+    pub fn verify(&mut self, nonce: &str) -> bool {
+        if self.challenge.as_deref() == Some(nonce) {
+            self.challenge = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn poll_interval(&self, max: Duration) -> Duration {
+        let max = match self.heartbeat {
+            Duration::ZERO => max,
+            heartbeat => max.min(heartbeat),
+        };
+        let max = match self.rate {
+            Some(rate) if self.throttled => max.min(Duration::from_secs(1) / rate),
+            _ => max,
+        };
+        match self.conflator {
+            Some(_) => max.min(self.conflate_every),
+            None => max,
+        }
+    }
+
+    fn budget(&mut self) -> usize {
+        let Some(rate) = self.rate else {
+            return DRAIN_BUDGET;
+        };
+        let rate = f64::from(rate);
+        let refill = self.last_refill.elapsed().as_secs_f64() * rate;
+        self.allowance = (self.allowance + refill).min(rate);
+        self.last_refill = Instant::now();
+        (self.allowance.max(0.0) as usize).min(DRAIN_BUDGET)
+    }
+
+    fn on_sent(&mut self, outgoing: &mut Vec<MarketEvent>) {
+        for event in outgoing.iter() {
+            if let MarketEvent::Quote(quote) = event
+                && let Some(seq) = quote.seq
+            {
+                self.last_seqs.insert(quote.ticker.clone(), seq);
+            }
+        }
+        if !outgoing.is_empty() {
+            self.last_sent = Instant::now();
+        } else if !self.heartbeat.is_zero() && self.last_sent.elapsed() >= self.heartbeat {
+            outgoing.push(MarketEvent::Control(ControlEvent::heartbeat(
+                self.last_seqs.clone(),
+            )));
+            self.last_sent = Instant::now();
+        }
+        if self.rate.is_some() {
+            self.allowance -= outgoing.len() as f64;
+        }
+    }
+
+    pub fn on_event(&mut self, event: MarketEvent) -> Vec<MarketEvent> {
+        match event {
+            MarketEvent::Quote(quote) => {
+                if let Some(aggregator) = self.bars.as_mut() {
+                    self.last_quote = Some((quote.timestamp, Instant::now()));
+                    aggregator
+                        .update(&quote)
+                        .into_iter()
+                        .map(MarketEvent::Bar)
+                        .collect()
+                } else if let Some(conflator) = self.conflator.as_mut() {
+                    conflator.push(quote);
+                    Vec::new()
+                } else {
+                    vec![MarketEvent::Quote(quote)]
+                }
+            }
+            event => vec![event],
+        }
+    }
+
+    pub fn on_tick(&mut self) -> Vec<MarketEvent> {
+        let mut out = Vec::new();
+        if let Some(aggregator) = self.bars.as_mut()
+            && let Some((timestamp, at)) = self.last_quote
+        {
+            let now = timestamp + at.elapsed().as_millis() as u64;
+            out.extend(aggregator.advance(now).into_iter().map(MarketEvent::Bar));
+        }
+        if let Some(aggregator) = self.bars.as_mut()
+            && self.last_bars.elapsed() >= BAR_PUBLISH_INTERVAL
+        {
+            out.extend(aggregator.drain_updated().into_iter().map(MarketEvent::Bar));
+            self.last_bars = Instant::now();
+        }
+        if let Some(conflator) = self.conflator.as_mut()
+            && self.last_flush.elapsed() >= self.conflate_every
+        {
+            out.extend(conflator.drain().into_iter().map(MarketEvent::Quote));
+            self.last_flush = Instant::now();
+        }
+        out
+    }
+
+    pub fn drain(&mut self, rx: &QueueReceiver) -> (Vec<MarketEvent>, Drain) {
+        let mut outgoing = Vec::new();
+        let mut status = Drain::Pending;
+        let budget = match self.challenge {
+            Some(_) => DRAIN_BUDGET,
+            None => self.budget(),
+        };
+        for _ in 0..budget {
+            match rx.try_recv() {
+                Ok(_) if self.challenge.is_some() => {}
+                Ok(event) => outgoing.extend(self.on_event(event)),
+                Err(TryRecvError::Empty) => {
+                    status = Drain::Idle;
+                    break;
+                }
+                Err(TryRecvError::Disconnected) => return (outgoing, Drain::Closed),
+            }
+        }
+        if let Some(nonce) = &self.challenge {
+            rx.take_overflow();
+            if self
+                .last_challenge
+                .is_none_or(|at| at.elapsed() >= CHALLENGE_INTERVAL)
+            {
+                outgoing.push(MarketEvent::Control(ControlEvent::challenge(nonce)));
+                self.last_challenge = Some(Instant::now());
+            }
+            return (outgoing, status);
+        }
+        self.throttled = status == Drain::Pending && budget < DRAIN_BUDGET;
+        if self.throttled {
+            status = Drain::Idle;
+        } else {
+            for event in rx.take_overflow() {
+                outgoing.extend(self.on_event(event));
+            }
+        }
+        outgoing.extend(self.on_tick());
+        self.on_sent(&mut outgoing);
+        (outgoing, status)
+    }
+
+    pub fn finish(&mut self, rx: &QueueReceiver) -> Vec<MarketEvent> {
+        let mut outgoing = Vec::new();
+        if self.challenge.is_some() {
+            return outgoing;
+        }
+        while let Ok(event) = rx.try_recv() {
+            outgoing.extend(self.on_event(event));
+        }
+        for event in rx.take_overflow() {
+            outgoing.extend(self.on_event(event));
+        }
+        if let Some(aggregator) = self.bars.as_mut() {
+            outgoing.extend(aggregator.drain_updated().into_iter().map(MarketEvent::Bar));
+        }
+        if let Some(conflator) = self.conflator.as_mut() {
+            outgoing.extend(conflator.drain().into_iter().map(MarketEvent::Quote));
+        }
+        outgoing
+    }
+}
+
+pub fn ping_ticket(payload: &[u8]) -> Option<StreamTicket> {
+    std::str::from_utf8(payload).ok().and_then(parse_ping)
+}
+
+pub fn proof_nonce(payload: &[u8]) -> Option<(u64, String)> {
+    std::str::from_utf8(payload).ok().and_then(parse_proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use quote_core::{OverflowPolicy, StockQuote};
+
+    use super::*;
+    use crate::metrics::Metrics;
+    use crate::queue::client_queue;
+
+    fn quote(ticker: &str, price: f64) -> MarketEvent {
+        MarketEvent::Quote(StockQuote {
+            ticker: ticker.to_string(),
+            price,
+            volume: 1,
+            timestamp: 0,
+            seq: None,
+        })
+    }
+
+    #[test]
+    fn conflates_quotes_until_flush() {
+        let options = StreamOptions {
+            conflate: Some(Duration::from_millis(20)),
+            ..StreamOptions::default()
+        };
+        let mut state = StreamState::new(&options, Duration::ZERO);
+        for price in 1..=100 {
+            assert!(state.on_event(quote("AAPL", price as f64)).is_empty());
+        }
+        std::thread::sleep(Duration::from_millis(25));
+        assert_eq!(state.on_tick(), vec![quote("AAPL", 100.0)]);
+        assert!(state.on_tick().is_empty());
+    }
+
+    #[test]
+    fn completes_bars_without_further_quotes() {
+        let options = StreamOptions {
+            bars: Some(Duration::from_millis(50)),
+            ..StreamOptions::default()
+        };
+        let mut state = StreamState::new(&options, Duration::ZERO);
+        let mut first = quote("AAPL", 10.0);
+        if let MarketEvent::Quote(quote) = &mut first {
+            quote.timestamp = quote_core::now_millis();
+        }
+        assert!(state.on_event(first).is_empty());
+        assert!(state.on_tick().is_empty());
+        std::thread::sleep(Duration::from_millis(110));
+        assert!(matches!(
+            state.on_tick().as_slice(),
+            [MarketEvent::Bar(bar)] if bar.complete && bar.close == 10.0
+        ));
+        assert!(state.on_tick().is_empty());
+    }
+
+    #[test]
+    fn sends_heartbeat_with_last_sequence_when_idle() {
+        let (queue, rx) = client_queue(
+            "127.0.0.1:1".parse().unwrap(),
+            16,
+            OverflowPolicy::default(),
+            Arc::new(Metrics::default()),
+        );
+        let mut state = StreamState::new(&StreamOptions::default(), Duration::from_millis(20));
+        let sequenced = [("AAPL", 7), ("MSFT", 3)].map(|(ticker, seq)| {
+            let mut event = quote(ticker, 1.0);
+            if let MarketEvent::Quote(quote) = &mut event {
+                quote.seq = Some(seq);
+            }
+            event
+        });
+        for event in &sequenced {
+            queue.push(event.clone());
+        }
+        assert_eq!(state.drain(&rx).0, sequenced.to_vec());
+        assert!(state.drain(&rx).0.is_empty());
+
+        std::thread::sleep(Duration::from_millis(25));
+        let (outgoing, _) = state.drain(&rx);
+        let expected = [("AAPL".to_string(), 7), ("MSFT".to_string(), 3)].into();
+        assert!(matches!(
+            outgoing.as_slice(),
+            [MarketEvent::Control(ControlEvent { seqs, .. })] if *seqs == expected
+        ));
+        assert!(state.drain(&rx).0.is_empty());
+    }
+
+    #[test]
+    fn withholds_events_until_proof_of_receipt() {
+        let (queue, rx) = client_queue(
+            "127.0.0.1:1".parse().unwrap(),
+            16,
+            OverflowPolicy::default(),
+            Arc::new(Metrics::default()),
+        );
+        let mut state = StreamState::new(&StreamOptions::default(), Duration::ZERO);
+        state.require_proof("c0ffee".to_string());
+        queue.push(quote("AAPL", 1.0));
+        let (outgoing, _) = state.drain(&rx);
+        assert_eq!(outgoing.len(), 1);
+        assert!(matches!(
+            &outgoing[0],
+            MarketEvent::Control(ControlEvent { nonce: Some(nonce), .. }) if nonce == "c0ffee"
+        ));
+        queue.push(quote("AAPL", 2.0));
+        assert!(state.drain(&rx).0.is_empty());
+
+        assert!(!state.verify("guess"));
+        assert!(state.verify("c0ffee"));
+        queue.push(quote("AAPL", 3.0));
+        assert_eq!(state.drain(&rx).0, vec![quote("AAPL", 3.0)]);
+    }
+
+    #[test]
+    fn limits_messages_per_second() {
+        let (queue, rx) = client_queue(
+            "127.0.0.1:1".parse().unwrap(),
+            64,
+            OverflowPolicy::default(),
+            Arc::new(Metrics::default()),
+        );
+        let mut state = StreamState::new(&StreamOptions::default(), Duration::ZERO);
+        state.limit_rate(20);
+        for price in 1..=25 {
+            queue.push(quote("AAPL", price as f64));
+        }
+        let (outgoing, status) = state.drain(&rx);
+        assert_eq!(outgoing.len(), 20);
+        assert_eq!(status, Drain::Idle);
+        assert!(state.drain(&rx).0.is_empty());
+        assert_eq!(
+            state.poll_interval(DISPATCH_TIMEOUT),
+            Duration::from_millis(50)
+        );
+
+        std::thread::sleep(Duration::from_millis(120));
+        let (outgoing, _) = state.drain(&rx);
+        assert!((1..5).contains(&outgoing.len()));
+    }
+}
@@ -0,0 +1,119 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use quote_core::tls;
+use rustls::RootCertStore;
+use rustls::pki_types::CertificateDer;
+use rustls::server::WebPkiClientVerifier;
+
+use crate::config::ConfigError;
+
+pub fn server_config(
+    cert: &Path,
+    key: &Path,
+    client_ca: Option<&Path>,
+) -> Result<Arc<rustls::ServerConfig>, ConfigError> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(tls_error)?;
+    let builder = match client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(path)? {
+                roots.add(cert).map_err(tls_error)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(tls_error)?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let key = tls::load_key(key).map_err(ConfigError::Parse)?;
+    let config = builder
+        .with_single_cert(load_certs(cert)?, key)
+        .map_err(tls_error)?;
+    Ok(Arc::new(config))
+}
+
+pub type Control = tls::Control<rustls::ServerConnection>;
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, ConfigError> {
+    tls::load_certs(path).map_err(ConfigError::Parse)
+}
+
+fn tls_error(err: impl std::fmt::Display) -> ConfigError {
+    ConfigError::Parse(format!("tls: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpStream, UdpSocket};
+
+    use quote_core::test_support::write_pki;
+    use quote_core::{QuoteSource, UpstreamConfig, UpstreamSource, parse_accepted};
+
+    use super::*;
+    use crate::ServerConfig;
+    use crate::handle::test_servers;
+
+    #[test]
+    fn serves_tls_and_verifies_client_certificates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        write_pki(dir.path());
+        assert!(server_config(&path("server.key"), &path("server.key"), None).is_err());
+        assert!(server_config(&path("server.pem"), &path("missing.key"), None).is_err());
+        let config = ServerConfig {
+            tls: Some(
+                server_config(
+                    &path("server.pem"),
+                    &path("server.key"),
+                    Some(&path("ca.pem")),
+                )
+                .unwrap(),
+            ),
+            ..ServerConfig::default()
+        };
+        let identity = Some((path("client.pem"), path("client.key")));
+        let identity = identity
+            .as_ref()
+            .map(|(cert, key)| (cert.as_path(), key.as_path()));
+        let trusted = tls::client_config(&path("ca.pem"), identity).unwrap();
+        let anonymous = tls::client_config(&path("ca.pem"), None).unwrap();
+
+        for (async_runtime, server) in test_servers(&config) {
+            let server_addr = server.local_addr().to_string();
+            let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let request = |client: &Arc<rustls::ClientConfig>| {
+                let socket = TcpStream::connect(server.local_addr()).unwrap();
+                let mut control =
+                    BufReader::new(tls::Control::connect(socket, &server_addr, Some(client))?);
+                let command = format!("STREAM udp://{} AAPL\n", udp.local_addr().unwrap());
+                control.get_mut().write_all(command.as_bytes())?;
+                let mut reply = String::new();
+                control.read_line(&mut reply).map(|_| reply)
+            };
+
+            let reply = request(&trusted).unwrap();
+            assert!(parse_accepted(&reply).is_ok(), "async={async_runtime}");
+            let rejected = request(&anonymous);
+            assert!(
+                !matches!(&rejected, Ok(reply) if !reply.is_empty()),
+                "{rejected:?}"
+            );
+
+            let mut upstream = UpstreamConfig::new(&server_addr, &["AAPL".to_string()]);
+            assert!(UpstreamSource::connect(upstream.clone()).is_err());
+            upstream.tls = Some(trusted.clone());
+            let mut source = UpstreamSource::connect(upstream).unwrap();
+            let received = (0..50)
+                .flat_map(|_| source.next_batch().unwrap().unwrap())
+                .find(|event| event.ticker().is_some());
+            assert_eq!(received.unwrap().ticker(), Some("AAPL"));
+            server.shutdown().unwrap();
+        }
+    }
+}